@@ -1,44 +1,67 @@
 use std::f32::consts::PI;
 
 use bevy::{
-    input::{common_conditions::input_toggle_active, gamepad::GamepadEvent},
-    prelude::*,
-    utils::HashMap,
+    input::common_conditions::input_toggle_active, prelude::*, reflect::TypeUuid, utils::HashMap,
 };
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checkpoint::LastCheckpoint,
+    controls::{Action, VehicleInput},
+    drivetrain::{update_engine, DrivetrainConfig, Transmission},
+    game_state::GameState,
+    hitch::{self, CoupleVehicles, Hitch, HitchConfig, UncoupleVehicles},
+};
+
+/// Radius of every tire, in meters.
+pub const TIRE_RADIUS: f32 = 0.5;
+/// Speed, in meters per second, below which rolling resistance fades out as the tire stops.
+const ROLLING_RESISTANCE_FADE_SPEED: f32 = 0.5;
+/// How heavy a wheel feels to the engine and brakes, as a mass at the tread, in kilograms.
+const WHEEL_MASS: f32 = 2.0;
+/// Slowest speed, in meters per second, that tire slip is measured against.
+const TIRE_SLIP_REFERENCE_SPEED: f32 = 1.0;
 
 pub struct CarPlugin;
 
 impl Plugin for CarPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<AddForce>()
+            .add_event::<ResetRig>()
+            .add_event::<ResetToCheckpoint>()
             .add_systems(
                 Update,
                 (
-                    turn_tires,
+                    (steer_vehicles, turn_tires).chain(),
                     calculate_tire_distances_to_ground,
-                    calculate_tire_acceleration_and_braking_forces
-                        .after(calculate_tire_distances_to_ground),
-                    calculate_tire_turning_forces.after(calculate_tire_distances_to_ground),
+                    calculate_tire_drive_and_brake_torques.after(update_engine),
                     calculate_tire_suspension_forces.after(calculate_tire_distances_to_ground),
-                    calculate_tire_friction.after(calculate_tire_distances_to_ground),
+                    calculate_tire_grip_forces
+                        .after(calculate_tire_drive_and_brake_torques)
+                        .after(calculate_tire_suspension_forces),
+                    calculate_rolling_resistance.after(calculate_tire_suspension_forces),
+                    calculate_air_drag,
                     move_tires_to_ground.after(calculate_tire_distances_to_ground),
-                    reset_car,
+                    request_reset.run_if(in_state(GameState::Playing)),
+                    reset_car.after(request_reset),
                     (
                         sum_all_forces,
-                        draw_tire_force_gizmos.run_if(input_toggle_active(true, KeyCode::L)),
+                        draw_tire_force_gizmos
+                            .run_if(input_toggle_active(true, Action::ToggleForceGizmos)),
                     )
-                        .after(calculate_tire_acceleration_and_braking_forces)
-                        .after(calculate_tire_turning_forces)
-                        .after(calculate_tire_suspension_forces)
-                        .after(calculate_tire_friction),
+                        .after(calculate_tire_grip_forces)
+                        .after(calculate_rolling_resistance)
+                        .after(calculate_air_drag),
                 ),
             )
             .register_type::<Car>()
             .register_type::<Trailer>()
             .register_type::<Drivable>()
             .register_type::<Tire>()
+            .register_type::<SteeringAngle>()
             .register_type::<CameraPosition>()
+            .register_type::<SpawnPoint>()
             .register_type::<VehicleConfig>();
     }
 }
@@ -53,15 +76,43 @@ pub struct Trailer;
 
 #[derive(Component, Default, Reflect)]
 #[reflect(Component)]
-struct Drivable;
+pub struct Drivable;
+
+/// How far a drivable's steering wheels are turned, in radians. Positive is to the left.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct SteeringAngle(pub f32);
 
-#[derive(Component, Reflect)]
+#[derive(Component, Reflect, Clone, Serialize, Deserialize)]
 #[reflect(Component)]
+#[serde(default)]
 pub struct Tire {
     pub connected_to_engine: bool,
     pub turns: bool,
+    /// Peak friction coefficient: the most force the tire can make, as a fraction of the load on
+    /// it. This is `D` in the magic formula.
     pub grip: f32,
+    /// How quickly grip builds up with slip, `B` in the magic formula.
+    pub stiffness: f32,
+    /// How much grip falls away past the peak, `C` in the magic formula.
+    pub shape: f32,
+    /// How sharp the peak is, `E` in the magic formula. At most 1.
+    pub curvature: f32,
+    #[serde(skip)]
     pub distance_to_ground: Option<f32>,
+    /// Newtons pressing the tire into the ground, from the suspension.
+    #[serde(skip)]
+    pub load: f32,
+    /// How fast the tread is moving, in meters per second, which differs from the ground speed
+    /// when the tire spins or locks up.
+    #[serde(skip)]
+    pub wheel_speed: f32,
+    /// Torque from the engine this frame, in newton meters.
+    #[serde(skip)]
+    pub drive_torque: f32,
+    /// Most torque the brakes can apply this frame, in newton meters.
+    #[serde(skip)]
+    pub brake_torque: f32,
 }
 
 #[derive(Component, Reflect, Default)]
@@ -75,8 +126,15 @@ impl Default for Tire {
         Self {
             connected_to_engine: false,
             turns: false,
-            grip: 0.7,
+            grip: 1.0,
+            stiffness: 10.0,
+            shape: 1.5,
+            curvature: 0.6,
             distance_to_ground: None,
+            load: 0.0,
+            wheel_speed: 0.0,
+            drive_torque: 0.0,
+            brake_torque: 0.0,
         }
     }
 }
@@ -85,25 +143,182 @@ impl Default for Tire {
 #[reflect(Component)]
 pub struct CameraPosition;
 
-#[derive(Component, Default, Reflect, Clone, Copy)]
+/// Where the rig starts. The car is placed just ahead of the entity's transform, facing along its
+/// +X axis, with each trailer behind it.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct SpawnPoint {
+    /// The car followed by each trailer, in the order they're hitched.
+    pub vehicles: Vec<Entity>,
+    /// Articulation angle, in radians, of each trailer relative to the vehicle towing it. Positive
+    /// angles swing the trailer anticlockwise seen from above, like `ArticulationAngle`. Trailers
+    /// without an angle start straight.
+    pub hitch_angles: Vec<f32>,
+    /// Vehicles that start unhitched somewhere else in the level, and where they start.
+    #[reflect(ignore)]
+    pub free_vehicles: Vec<VehiclePose>,
+}
+
+impl SpawnPoint {
+    /// Every vehicle in the level: the rig followed by the free vehicles.
+    pub fn all_vehicles(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.vehicles.iter().copied().chain(
+            self.free_vehicles
+                .iter()
+                .map(|free_vehicle| free_vehicle.vehicle),
+        )
+    }
+}
+
+#[derive(Component, Default, Reflect, Clone, Serialize, Deserialize, TypeUuid)]
 #[reflect(Component)]
+#[uuid = "5d0c7a4e-2f9b-4b8e-9a47-3c1e6f0b8d21"]
 pub struct VehicleConfig {
     pub height: f32,
     pub width: f32,
     pub length: f32,
-    pub wheelbase: f32,
-    pub wheel_offset: f32,
+    pub axles: Vec<AxleConfig>,
     pub spring_offset: f32,
     pub spring_power: f32,
     pub shock: f32,
+    /// Speed, in meters per second, at which steering is down to
+    /// `SteeringConfig::high_speed_lock`.
     pub max_speed: f32,
-    pub max_force: f32,
+    #[serde(default)]
+    pub drivetrain: DrivetrainConfig,
     pub turn_radius: f32,
-    pub anchor_point: Vec3,
+    /// How quickly the steering wheels turn towards where they're steered.
+    #[serde(default)]
+    pub steering: SteeringConfig,
+    #[serde(default)]
+    pub brakes: BrakeConfig,
+    #[serde(default)]
+    pub resistance: ResistanceConfig,
+    /// Where a vehicle in front hitches onto this one, relative to its center.
+    #[serde(default)]
+    pub front_hitch: Option<Vec3>,
+    /// Where a vehicle behind hitches onto this one, relative to its center.
+    #[serde(default)]
+    pub rear_hitch: Option<Vec3>,
+    /// How the front hitch moves once it's coupled to the vehicle in front.
+    #[serde(default)]
+    pub hitch: HitchConfig,
+    /// Scene drawn for the vehicle body, e.g. `"trailer.glb#Scene0"`.
+    #[serde(default)]
+    pub model: Option<String>,
     pub scale: f32,
     pub starting_tire_grip: f32,
 }
 
+impl VehicleConfig {
+    /// Copies the tuning from `other`, such as a saved preset, keeping what makes this vehicle
+    /// what it is: its axles, hitches and model. Those decide which tires and joints were spawned,
+    /// so they can't change on a live vehicle.
+    pub fn apply_tuning(&mut self, other: &VehicleConfig) {
+        *self = VehicleConfig {
+            axles: self.axles.clone(),
+            front_hitch: self.front_hitch,
+            rear_hitch: self.rear_hitch,
+            hitch: self.hitch.clone(),
+            model: self.model.clone(),
+            scale: self.scale,
+            ..other.clone()
+        };
+    }
+
+    /// Where along the vehicle it turns about: the middle of the axles that don't steer, or
+    /// `None` if every axle steers.
+    pub fn turning_center(&self) -> Option<f32> {
+        let fixed_axles: Vec<f32> = self
+            .axles
+            .iter()
+            .filter(|axle| !axle.steering)
+            .map(|axle| axle.position)
+            .collect();
+        if fixed_axles.is_empty() {
+            return None;
+        }
+        Some(fixed_axles.iter().sum::<f32>() / fixed_axles.len() as f32)
+    }
+}
+
+#[derive(Default, Reflect, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxleConfig {
+    /// Distance of the axle in front of (positive) or behind (negative) the vehicle's center.
+    pub position: f32,
+    /// Distance between the left and right tire. An axle with no track width has a single
+    /// tire in the middle, like the front of a three-wheeler.
+    pub track_width: f32,
+    pub steering: bool,
+    pub driven: bool,
+}
+
+/// How hard a drivable can brake. Torques are in newton meters, across all of its tires.
+#[derive(Reflect, Clone, Serialize, Deserialize)]
+pub struct BrakeConfig {
+    /// Most torque the brakes can apply with the pedal all the way down.
+    pub max_torque: f32,
+    /// Share of the brake torque that goes to the tires in front of the center, from 0 to 1.
+    pub front_bias: f32,
+    /// Torque the handbrake adds to each of the car's rear tires, enough to lock them.
+    pub handbrake_torque: f32,
+}
+
+impl Default for BrakeConfig {
+    fn default() -> Self {
+        Self {
+            max_torque: 100.0,
+            front_bias: 0.6,
+            handbrake_torque: 50.0,
+        }
+    }
+}
+
+/// What slows a drivable down when nothing is driving or braking it.
+#[derive(Reflect, Clone, Serialize, Deserialize)]
+pub struct ResistanceConfig {
+    /// Rolling resistance of each tire, as a fraction of the weight on it.
+    pub rolling: f32,
+    /// Air drag, in newtons for every (meter per second) squared of speed.
+    pub drag: f32,
+}
+
+impl Default for ResistanceConfig {
+    fn default() -> Self {
+        Self {
+            rolling: 0.1,
+            drag: 0.02,
+        }
+    }
+}
+
+/// How the steering wheels follow the player's input. Rates are in radians per second.
+#[derive(Reflect, Clone, Serialize, Deserialize)]
+pub struct SteeringConfig {
+    /// How quickly the wheels turn while steering.
+    pub rate: f32,
+    /// How quickly the wheels straighten up when nothing is steering.
+    pub centering_rate: f32,
+    /// Fraction of `turn_radius` still available at `max_speed`, so the car is less twitchy at
+    /// speed. Full lock is always available at a standstill.
+    pub high_speed_lock: f32,
+    /// How much the inside wheel turns more than the outside one, from 0 for both wheels at the
+    /// same angle to 1 for full Ackermann geometry, where every wheel rolls around the same
+    /// point and nothing scrubs.
+    pub ackermann: f32,
+}
+
+impl Default for SteeringConfig {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            centering_rate: 1.5,
+            high_speed_lock: 0.3,
+            ackermann: 1.0,
+        }
+    }
+}
+
 #[derive(Bundle, Default)]
 struct DrivableBundle {
     transform: TransformBundle,
@@ -117,7 +332,11 @@ struct DrivableBundle {
     external_force: ExternalForce,
     name: Name,
     friction: Friction,
+    active_events: ActiveEvents,
+    contact_force_event_threshold: ContactForceEventThreshold,
     vehicle_config: VehicleConfig,
+    steering_angle: SteeringAngle,
+    transmission: Transmission,
 }
 
 #[derive(Bundle, Default)]
@@ -128,9 +347,117 @@ struct TireBundle {
     visibility: VisibilityBundle,
 }
 
+/// Lays out a car followed by each of its trailers, hitched nose to tail.
+///
+/// The car is placed just ahead of `pose`, facing along its +X axis, and each trailer is swung
+/// about its front hitch by its entry in `hitch_angles`, clamped to what the hitch allows.
+pub fn rig_layout(
+    vehicle_configs: &[&VehicleConfig],
+    pose: Transform,
+    hitch_angles: &[f32],
+) -> Vec<Transform> {
+    let mut transforms: Vec<Transform> = Vec::new();
+    for (i, vehicle_config) in vehicle_configs.iter().enumerate() {
+        let transform = match transforms.last() {
+            None => {
+                pose * Transform::from_xyz(
+                    vehicle_config.length + vehicle_config.rear_hitch.unwrap_or_default().x,
+                    vehicle_config.height,
+                    0.0,
+                )
+            }
+            Some(previous) => {
+                let previous_config = vehicle_configs[i - 1];
+                let hitch_angle = hitch_angles.get(i - 1).copied().unwrap_or_default();
+                let max_yaw = vehicle_config.hitch.max_yaw;
+                let rotation =
+                    previous.rotation * Quat::from_rotation_y(hitch_angle.clamp(-max_yaw, max_yaw));
+                // hitches line up on the ground, each vehicle keeps its own ride height
+                let flat =
+                    |anchor: Option<Vec3>| anchor.unwrap_or_default() * Vec3::new(1.0, 0.0, 1.0);
+                let hitch =
+                    previous.translation + previous.rotation * flat(previous_config.rear_hitch);
+                let mut translation = hitch - rotation * flat(vehicle_config.front_hitch);
+                translation.y = pose.translation.y + vehicle_config.height;
+                Transform::from_translation(translation).with_rotation(rotation)
+            }
+        };
+        transforms.push(transform);
+    }
+    transforms
+}
+
+/// Spawns a car followed by each of its trailers, hitching every vehicle to the one before it,
+/// laid out by `rig_layout`. Returns the spawned vehicles in the same order as `vehicle_configs`.
+pub fn spawn_rig(
+    commands: &mut Commands,
+    vehicle_configs: &[VehicleConfig],
+    pose: Transform,
+    hitch_angles: &[f32],
+    meshes: &mut ResMut<Assets<Mesh>>,
+    tire_material: Handle<StandardMaterial>,
+    asset_server: &Res<AssetServer>,
+) -> Vec<Entity> {
+    let layout = rig_layout(
+        &vehicle_configs.iter().collect::<Vec<_>>(),
+        pose,
+        hitch_angles,
+    );
+    let mut entities: Vec<Entity> = Vec::new();
+    for (i, (vehicle_config, transform)) in vehicle_configs.iter().zip(layout).enumerate() {
+        let is_car = i == 0;
+        let name = match i {
+            0 => "Car".to_string(),
+            1 => "Trailer".to_string(),
+            _ => format!("Trailer {}", i),
+        };
+        entities.push(spawn_vehicle(
+            commands,
+            vehicle_config.clone(),
+            transform,
+            meshes,
+            tire_material.clone(),
+            &name,
+            is_car,
+            asset_server,
+        ));
+    }
+
+    for i in 1..entities.len() {
+        let (Some(front_anchor), Some(rear_anchor)) = (
+            vehicle_configs[i - 1].rear_hitch,
+            vehicle_configs[i].front_hitch,
+        ) else {
+            warn!(
+                "can't hitch vehicle {} to vehicle {}, missing an anchor",
+                i,
+                i - 1
+            );
+            continue;
+        };
+        commands.entity(entities[i]).insert(ImpulseJoint::new(
+            entities[i - 1],
+            hitch::hitch_joint(front_anchor, rear_anchor, &vehicle_configs[i].hitch),
+        ));
+        let (front, rear) = (entities[i - 1], entities[i]);
+        // the hitches were only just spawned, so link them up once the commands have run
+        commands.add(move |world: &mut World| {
+            if let Some(mut front_hitch) = world.get_mut::<Hitch>(front) {
+                front_hitch.towing = Some(rear);
+            }
+            if let Some(mut rear_hitch) = world.get_mut::<Hitch>(rear) {
+                rear_hitch.towed_by = Some(front);
+            }
+        });
+    }
+    entities
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_vehicle(
     commands: &mut Commands,
     vehicle_config: VehicleConfig,
+    transform: Transform,
     meshes: &mut ResMut<Assets<Mesh>>,
     tire_material: Handle<StandardMaterial>,
     name: &str,
@@ -138,44 +465,45 @@ pub fn spawn_vehicle(
     asset_server: &Res<AssetServer>,
 ) -> Entity {
     let id = commands
-        .spawn((DrivableBundle {
-            transform: TransformBundle::from_transform(Transform::from_xyz(
-                if is_car {
-                    vehicle_config.length + vehicle_config.anchor_point.x
-                } else {
-                    -(vehicle_config.length + vehicle_config.anchor_point.x)
-                },
-                vehicle_config.height,
-                0.,
-            )),
-            name: Name::from(name),
-            friction: Friction::coefficient(0.5),
-            vehicle_config,
-            collider: Collider::cuboid(
-                vehicle_config.length,
-                vehicle_config.height,
-                vehicle_config.width,
-            ),
-            collider_mass_properties: ColliderMassProperties::Density(1.0),
-            ..default()
-        },))
+        .spawn((
+            DrivableBundle {
+                transform: TransformBundle::from_transform(transform),
+                name: Name::from(name),
+                friction: Friction::coefficient(0.5),
+                // the threshold is set from the level's penalty rules once the vehicle is spawned
+                active_events: ActiveEvents::CONTACT_FORCE_EVENTS,
+                contact_force_event_threshold: ContactForceEventThreshold(f32::MAX),
+                vehicle_config: vehicle_config.clone(),
+                collider: Collider::cuboid(
+                    vehicle_config.length,
+                    vehicle_config.height,
+                    vehicle_config.width,
+                ),
+                collider_mass_properties: ColliderMassProperties::Density(1.0),
+                ..default()
+            },
+            Hitch {
+                front_anchor: vehicle_config.front_hitch,
+                rear_anchor: vehicle_config.rear_hitch,
+                config: vehicle_config.hitch.clone(),
+                ..default()
+            },
+        ))
         .with_children(|child_builder| {
             // vehicle model
-            child_builder.spawn((
-                SceneBundle {
-                    scene: if is_car {
-                        asset_server.load::<Scene, &str>("scene.gltf#Scene0")
-                    } else {
-                        asset_server.load::<Scene, &str>("trailer.glb#Scene0")
+            if let Some(model) = &vehicle_config.model {
+                child_builder.spawn((
+                    SceneBundle {
+                        scene: asset_server.load::<Scene, &str>(model),
+                        transform: Transform::from_xyz(0.0, 0.0, 0.0),
+                        ..default()
                     },
-                    transform: Transform::from_xyz(0.0, 0.0, 0.0),
-                    ..default()
-                },
-                Name::from("Vehicle Model"),
-            ));
+                    Name::from("Vehicle Model"),
+                ));
+            }
 
             let tire_mesh = meshes.add(Mesh::from(shape::Cylinder {
-                radius: 0.5,
+                radius: TIRE_RADIUS,
                 height: 0.5,
                 ..default()
             }));
@@ -185,93 +513,34 @@ pub fn spawn_vehicle(
                 transform: Transform::from_rotation(Quat::from_axis_angle(Vec3::X, PI / 2.0)),
                 ..default()
             };
-            child_builder
-                .spawn(TireBundle {
-                    transform_bundle: TransformBundle::from(Transform::from_xyz(
-                        vehicle_config.wheelbase + vehicle_config.wheel_offset,
-                        -vehicle_config.height / 6.0,
-                        vehicle_config.width * 1.1,
-                    )),
-                    tire: if is_car {
-                        Tire {
-                            connected_to_engine: true,
-                            turns: true,
-                            grip: vehicle_config.starting_tire_grip,
-                            ..default()
-                        }
-                    } else {
-                        Tire {
-                            grip: vehicle_config.starting_tire_grip,
-                            ..default()
-                        }
-                    },
-                    name: Name::from("Tire Front Right"),
-                    ..default()
-                })
-                .with_children(|tire_child_builder| {
-                    tire_child_builder.spawn(get_tire_material_mesh_bundle());
-                });
-            child_builder
-                .spawn(TireBundle {
-                    transform_bundle: TransformBundle::from(Transform::from_xyz(
-                        vehicle_config.wheelbase + vehicle_config.wheel_offset,
-                        -vehicle_config.height / 6.0,
-                        -vehicle_config.width * 1.1,
-                    )),
-                    tire: if is_car {
-                        Tire {
-                            connected_to_engine: true,
-                            turns: true,
-                            grip: vehicle_config.starting_tire_grip,
-                            ..default()
-                        }
-                    } else {
-                        Tire {
-                            grip: vehicle_config.starting_tire_grip,
+            for (axle_index, axle) in vehicle_config.axles.iter().enumerate() {
+                let sides: &[(f32, &str)] = if axle.track_width == 0.0 {
+                    &[(0.0, "Center")]
+                } else {
+                    &[(1.0, "Right"), (-1.0, "Left")]
+                };
+                for (side, side_name) in sides {
+                    child_builder
+                        .spawn(TireBundle {
+                            transform_bundle: TransformBundle::from(Transform::from_xyz(
+                                axle.position,
+                                -vehicle_config.height / 6.0,
+                                side * axle.track_width / 2.0,
+                            )),
+                            tire: Tire {
+                                connected_to_engine: axle.driven,
+                                turns: axle.steering,
+                                grip: vehicle_config.starting_tire_grip,
+                                ..default()
+                            },
+                            name: Name::from(format!("Tire Axle {} {}", axle_index + 1, side_name)),
                             ..default()
-                        }
-                    },
-                    name: Name::from("Tire Front Left"),
-                    ..default()
-                })
-                .with_children(|tire_child_builder| {
-                    tire_child_builder.spawn(get_tire_material_mesh_bundle());
-                });
-            child_builder
-                .spawn(TireBundle {
-                    transform_bundle: TransformBundle::from(Transform::from_xyz(
-                        -vehicle_config.wheelbase + vehicle_config.wheel_offset,
-                        -vehicle_config.height / 6.0,
-                        vehicle_config.width * 1.1,
-                    )),
-                    name: Name::from("Tire Back Right"),
-                    tire: Tire {
-                        grip: vehicle_config.starting_tire_grip,
-                        ..default()
-                    },
-                    ..default()
-                })
-                .with_children(|tire_child_builder| {
-                    tire_child_builder.spawn(get_tire_material_mesh_bundle());
-                });
-
-            child_builder
-                .spawn(TireBundle {
-                    transform_bundle: TransformBundle::from(Transform::from_xyz(
-                        -vehicle_config.wheelbase + vehicle_config.wheel_offset,
-                        -vehicle_config.height / 6.0,
-                        -vehicle_config.width * 1.1,
-                    )),
-                    name: Name::from("Tire Back Left"),
-                    tire: Tire {
-                        grip: vehicle_config.starting_tire_grip,
-                        ..default()
-                    },
-                    ..default()
-                })
-                .with_children(|tire_child_builder| {
-                    tire_child_builder.spawn(get_tire_material_mesh_bundle());
-                });
+                        })
+                        .with_children(|tire_child_builder| {
+                            tire_child_builder.spawn(get_tire_material_mesh_bundle());
+                        });
+                }
+            }
 
             for i in [-1.0, 1.0] {
                 for j in [-1.0, 1.0] {
@@ -357,62 +626,156 @@ struct AddForce {
     entity: Entity,
 }
 
+/// Restarts the attempt, putting every vehicle back at the spawn point.
+#[derive(Event)]
+pub struct ResetRig;
+
+/// Puts every vehicle back where it was at the last checkpoint, or at the spawn point if the car
+/// hasn't reached one yet.
+#[derive(Event)]
+pub struct ResetToCheckpoint;
+
+/// Where one vehicle in the rig is and what it's hitched behind.
+#[derive(Clone)]
+pub struct VehiclePose {
+    pub vehicle: Entity,
+    pub transform: Transform,
+    pub towed_by: Option<Entity>,
+}
+
+/// A snapshot of the whole rig that it can be put back into.
+#[derive(Clone)]
+pub struct RigPose(pub Vec<VehiclePose>);
+
+fn request_reset(
+    vehicle_input: Res<VehicleInput>,
+    mut reset_events: EventWriter<ResetRig>,
+    mut checkpoint_reset_events: EventWriter<ResetToCheckpoint>,
+) {
+    if vehicle_input.restart {
+        reset_events.send(ResetRig);
+    } else if vehicle_input.reset {
+        checkpoint_reset_events.send(ResetToCheckpoint);
+    }
+}
+
+/// The rig laid out at its spawn point, each vehicle hitched behind the one before it, and the
+/// free vehicles back where they started, unhitched.
+fn spawn_pose(
+    spawn_point: &SpawnPoint,
+    spawn_transform: &Transform,
+    vehicle_configs: &Query<&VehicleConfig>,
+) -> Option<RigPose> {
+    let configs = spawn_point
+        .vehicles
+        .iter()
+        .map(|vehicle| vehicle_configs.get(*vehicle).ok())
+        .collect::<Option<Vec<_>>>()?;
+    let layout = rig_layout(&configs, *spawn_transform, &spawn_point.hitch_angles);
+    Some(RigPose(
+        spawn_point
+            .vehicles
+            .iter()
+            .zip(layout)
+            .enumerate()
+            .map(|(i, (vehicle, transform))| VehiclePose {
+                vehicle: *vehicle,
+                transform,
+                towed_by: i.checked_sub(1).map(|front| spawn_point.vehicles[front]),
+            })
+            .chain(spawn_point.free_vehicles.iter().cloned())
+            .collect(),
+    ))
+}
+
+/// Puts the rig back at its spawn point or last checkpoint, hitched together the way it was.
+#[allow(clippy::too_many_arguments)]
 fn reset_car(
-    keys: Res<Input<KeyCode>>,
+    mut reset_events: EventReader<ResetRig>,
+    mut checkpoint_reset_events: EventReader<ResetToCheckpoint>,
+    last_checkpoint: Res<LastCheckpoint>,
+    spawn_points: Query<(&SpawnPoint, &Transform), Without<Drivable>>,
+    vehicle_configs: Query<&VehicleConfig>,
     mut drivables: Query<
         (
             &mut Transform,
-            &VehicleConfig,
+            &Hitch,
             &mut Velocity,
-            Option<&Car>,
             &mut ExternalForce,
+            &mut SteeringAngle,
+            &mut Transmission,
         ),
         With<Drivable>,
     >,
-    mut gamepad_evr: EventReader<GamepadEvent>,
+    mut tires: Query<(&Parent, &mut Tire)>,
+    mut couple_events: EventWriter<CoupleVehicles>,
+    mut uncouple_events: EventWriter<UncoupleVehicles>,
 ) {
-    let mut should_respawn = keys.just_pressed(KeyCode::R);
-    for ev in gamepad_evr.iter() {
-        match ev {
-            GamepadEvent::Button(button_ev) => {
-                if button_ev.value == 0.0 {
-                    continue;
-                }
-                match button_ev.button_type {
-                    GamepadButtonType::Start => {
-                        should_respawn = true;
-                    }
-                    _ => (),
-                }
-            }
-            _ => (),
-        }
+    let restart = reset_events.iter().count() > 0;
+    let to_checkpoint = checkpoint_reset_events.iter().count() > 0;
+    if !(restart || to_checkpoint) {
+        return;
     }
+    let pose = match &last_checkpoint.pose {
+        Some(checkpoint_pose) if !restart => checkpoint_pose.clone(),
+        _ => {
+            let Ok((spawn_point, spawn_transform)) = spawn_points.get_single() else {
+                return;
+            };
+            let Some(pose) = spawn_pose(spawn_point, spawn_transform, &vehicle_configs) else {
+                return;
+            };
+            pose
+        }
+    };
 
-    for (
-        mut drivable_transform,
-        drivable_config,
-        mut drivable_velocity,
-        maybe_car,
-        mut external_force,
-    ) in &mut drivables
-    {
-        let reseted_tranform = Transform::from_xyz(
-            match maybe_car {
-                Some(_) => drivable_config.length + drivable_config.anchor_point.x,
-                None => -drivable_config.length - drivable_config.anchor_point.x,
-            },
-            drivable_config.height,
-            0.,
-        );
+    for vehicle_pose in &pose.0 {
+        let Ok((
+            mut drivable_transform,
+            hitch,
+            mut drivable_velocity,
+            mut external_force,
+            mut steering_angle,
+            mut transmission,
+        )) = drivables.get_mut(vehicle_pose.vehicle)
+        else {
+            continue;
+        };
+        external_force.force = Vec3::ZERO;
+        external_force.torque = Vec3::ZERO;
+        drivable_velocity.linvel = Vec3::ZERO;
+        drivable_velocity.angvel = Vec3::ZERO;
+        steering_angle.0 = 0.0;
+        *transmission = Transmission::default();
+        *drivable_transform = vehicle_pose.transform;
+        for (_, mut tire) in tires
+            .iter_mut()
+            .filter(|(parent, _)| parent.get() == vehicle_pose.vehicle)
+        {
+            tire.wheel_speed = 0.0;
+        }
 
-        if should_respawn {
-            external_force.force = Vec3::ZERO;
-            external_force.torque = Vec3::ZERO;
-            drivable_velocity.linvel = Vec3::ZERO;
-            drivable_velocity.angvel = Vec3::ZERO;
-            drivable_transform.translation = reseted_tranform.translation;
-            drivable_transform.rotation = reseted_tranform.rotation;
+        // undo any hitching the player did since
+        let towing = pose
+            .0
+            .iter()
+            .find(|other| other.towed_by == Some(vehicle_pose.vehicle))
+            .map(|other| other.vehicle);
+        if let Some(current) = hitch.towing.filter(|current| Some(*current) != towing) {
+            uncouple_events.send(UncoupleVehicles { rear: current });
+        }
+        if hitch.towed_by != vehicle_pose.towed_by {
+            if hitch.towed_by.is_some() {
+                uncouple_events.send(UncoupleVehicles {
+                    rear: vehicle_pose.vehicle,
+                });
+            }
+            if let Some(front) = vehicle_pose.towed_by {
+                couple_events.send(CoupleVehicles {
+                    front,
+                    rear: vehicle_pose.vehicle,
+                });
+            }
         }
     }
 }
@@ -440,11 +803,12 @@ fn calculate_tire_distances_to_ground(
 }
 
 fn calculate_tire_suspension_forces(
-    tires: Query<(&GlobalTransform, &Parent, &Tire)>,
+    mut tires: Query<(&GlobalTransform, &Parent, &mut Tire)>,
     drivables: Query<(Entity, &Velocity, &Transform, &VehicleConfig), With<Drivable>>,
     mut add_forces: EventWriter<AddForce>,
 ) {
-    for (tire_transform, parent, tire) in &tires {
+    for (tire_transform, parent, mut tire) in &mut tires {
+        tire.load = 0.0;
         let (parent_entity, parent_velocity, parent_transform, parent_config) =
             drivables.get(parent.get()).unwrap();
         if let Some(hit_distance) = tire.distance_to_ground {
@@ -456,6 +820,7 @@ fn calculate_tire_suspension_forces(
             let offset = parent_config.spring_offset - hit_distance;
             let velocity = spring_direction.dot(tire_velocity);
             let force = (offset * parent_config.spring_power) - (velocity * parent_config.shock);
+            tire.load = force.max(0.0);
             add_forces.send(AddForce {
                 force: spring_direction * force,
                 point: tire_transform.translation(),
@@ -465,178 +830,270 @@ fn calculate_tire_suspension_forces(
     }
 }
 
-fn lookup_power(velocity: Velocity, max_speed: f32, max_force: f32) -> f32 {
-    let speed_ratio = velocity.linvel.length() / max_speed;
-    let lookup = if speed_ratio < 0.0 {
-        0.5
-    } else if speed_ratio >= 0.0 && speed_ratio < 0.4 {
-        -(-0.5 * speed_ratio + 0.3).log(10.0)
-    } else if speed_ratio >= 0.4 && speed_ratio <= 0.698 {
-        1.0
-    } else if speed_ratio > 0.698 && speed_ratio <= 1.0 {
-        (-5.0 * speed_ratio + 6.0).log(10.0) + 0.6
-    } else {
-        0.0
-    };
-    max_force * lookup
+/// Counts the tires in front of and behind each drivable's center, so brake force can be shared
+/// between them.
+fn count_front_and_rear_tires<'a>(
+    tires: impl Iterator<Item = (&'a Transform, Entity)>,
+) -> HashMap<Entity, (usize, usize)> {
+    let mut tire_counts = HashMap::new();
+    for (tire_transform, parent) in tires {
+        let (front, rear) = tire_counts.entry(parent).or_insert((0, 0));
+        if tire_transform.translation.x > 0.0 {
+            *front += 1;
+        } else {
+            *rear += 1;
+        }
+    }
+    tire_counts
 }
 
-fn calculate_tire_acceleration_and_braking_forces(
-    keys: Res<Input<KeyCode>>,
-    tires: Query<(&GlobalTransform, &Parent, &Tire)>,
-    drivables: Query<(Entity, &Velocity, &VehicleConfig), With<Drivable>>,
-    mut add_forces: EventWriter<AddForce>,
-    mut gamepad_evr: EventReader<GamepadEvent>,
-    mut left_trigger: Local<f32>,
-    mut right_trigger: Local<f32>,
+/// Works out how much torque the engine and brakes put through each tire this frame. The tire
+/// model turns it into grip in `calculate_tire_grip_forces`.
+fn calculate_tire_drive_and_brake_torques(
+    vehicle_input: Res<VehicleInput>,
+    mut tires: Query<(&Transform, &Parent, &mut Tire)>,
+    drivables: Query<(&VehicleConfig, &Transmission, Option<&Car>), With<Drivable>>,
 ) {
-    for (tire_transform, parent, tire) in &tires {
-        let (parent_entity, parent_velocity, parent_config) = drivables.get(parent.get()).unwrap();
-        let force_at_tire = tire_transform
-            .compute_transform()
-            .rotation
-            .mul_vec3(Vec3::new(
-                lookup_power(
-                    *parent_velocity,
-                    parent_config.max_speed,
-                    parent_config.max_force,
-                ),
-                0.0,
-                0.0,
-            ));
-        let mut multiplier = if keys.pressed(KeyCode::W) {
-            1.0
-        } else if keys.pressed(KeyCode::S) {
-            -1.0
+    let tire_counts = count_front_and_rear_tires(
+        tires
+            .iter()
+            .map(|(tire_transform, parent, _)| (tire_transform, parent.get())),
+    );
+    let driven_tire_counts = count_tires(
+        tires
+            .iter()
+            .filter(|(_, _, tire)| tire.connected_to_engine)
+            .map(|(_, parent, _)| parent.get()),
+    );
+    for (tire_local_transform, parent, mut tire) in &mut tires {
+        let (parent_config, transmission, car) = drivables.get(parent.get()).unwrap();
+
+        tire.drive_torque = if tire.connected_to_engine {
+            transmission.wheel_torque(&parent_config.drivetrain, vehicle_input.throttle)
+                / driven_tire_counts[&parent.get()] as f32
         } else {
             0.0
         };
-        for ev in gamepad_evr.iter() {
-            match ev {
-                GamepadEvent::Button(button_ev) => match button_ev.button_type {
-                    GamepadButtonType::RightTrigger2 => {
-                        *right_trigger = button_ev.value;
-                    }
-                    GamepadButtonType::LeftTrigger2 => {
-                        *left_trigger = -button_ev.value;
-                    }
-                    _ => (),
-                },
-                _ => (),
-            }
-        }
-        if *left_trigger != 0.0 {
-            multiplier = *left_trigger;
-        } else if *right_trigger != 0.0 {
-            multiplier = *right_trigger;
+
+        // only the car's brakes are connected to the pedals
+        if car.is_none() {
+            tire.brake_torque = 0.0;
+            continue;
         }
-        if tire.distance_to_ground.is_some() && tire.connected_to_engine {
-            add_forces.send(AddForce {
-                force: multiplier * force_at_tire,
-                point: tire_transform.translation(),
-                entity: parent_entity,
-            });
+        let is_front = tire_local_transform.translation.x > 0.0;
+        let (front_tires, rear_tires) = tire_counts[&parent.get()];
+        let share = match (front_tires, rear_tires) {
+            (0, _) | (_, 0) => 1.0 / (front_tires + rear_tires) as f32,
+            _ if is_front => parent_config.brakes.front_bias / front_tires as f32,
+            _ => (1.0 - parent_config.brakes.front_bias) / rear_tires as f32,
+        };
+        tire.brake_torque = vehicle_input.brake * share * parent_config.brakes.max_torque;
+        if vehicle_input.handbrake && !is_front {
+            tire.brake_torque += parent_config.brakes.handbrake_torque;
         }
     }
 }
 
-fn calculate_tire_friction(
+/// Counts the tires on each drivable, so engine torque can be shared evenly between them.
+fn count_tires(tire_parents: impl Iterator<Item = Entity>) -> HashMap<Entity, usize> {
+    let mut tire_counts = HashMap::new();
+    for parent in tire_parents {
+        *tire_counts.entry(parent).or_insert(0) += 1;
+    }
+    tire_counts
+}
+
+/// Slows each grounded tire down in the direction it rolls. The resistance fades out as the tire
+/// stops, instead of flipping direction every frame around zero.
+fn calculate_rolling_resistance(
     tires: Query<(&GlobalTransform, &Parent, &Tire)>,
-    drivables: Query<(Entity, &Velocity, &Transform, &ReadMassProperties), With<Drivable>>,
+    drivables: Query<(Entity, &Velocity, &Transform, &VehicleConfig), With<Drivable>>,
     mut add_forces: EventWriter<AddForce>,
 ) {
-    let coefficient_of_friction = 0.5;
     for (tire_transform, parent, tire) in &tires {
-        let (parent_entity, parent_velocity, parent_transform, ReadMassProperties(mass_properties)) =
+        let (parent_entity, parent_velocity, parent_transform, parent_config) =
             drivables.get(parent.get()).unwrap();
-        if tire.distance_to_ground.is_some() && tire.connected_to_engine {
-            if parent_velocity.linvel.length() > 0.0 {
-                let tire_velocity = parent_velocity.linear_velocity_at_point(
-                    tire_transform.translation(),
-                    parent_transform.translation,
-                );
-                let multiplier = if tire_velocity.dot(tire_transform.right()) < 0.0 {
-                    1.0
-                } else {
-                    -1.0
-                };
-                add_forces.send(AddForce {
-                    force: multiplier
-                        * tire_transform
-                            .compute_transform()
-                            .rotation
-                            .mul_vec3(Vec3::new(
-                                (mass_properties.mass / 4.0) * coefficient_of_friction * 9.81,
-                                0.0,
-                                0.0,
-                            )),
-                    point: tire_transform.translation(),
-                    entity: parent_entity,
-                });
-            }
+        if tire.distance_to_ground.is_none() {
+            continue;
         }
+        let tire_forward = tire_transform
+            .compute_transform()
+            .rotation
+            .mul_vec3(Vec3::X);
+        let tire_speed = parent_velocity
+            .linear_velocity_at_point(tire_transform.translation(), parent_transform.translation)
+            .dot(tire_forward);
+        let fade = (tire_speed / ROLLING_RESISTANCE_FADE_SPEED).clamp(-1.0, 1.0);
+        add_forces.send(AddForce {
+            force: -fade * parent_config.resistance.rolling * tire.load * tire_forward,
+            point: tire_transform.translation(),
+            entity: parent_entity,
+        });
     }
 }
 
-fn turn_tires(
-    drivables: Query<&VehicleConfig, With<Drivable>>,
-    keys: Res<Input<KeyCode>>,
-    mut tires: Query<(&mut Transform, &Tire, &Parent)>,
-    axes: Res<Axis<GamepadAxis>>,
-    gamepads: Res<Gamepads>,
+/// Pushes back on each drivable with the square of its speed.
+fn calculate_air_drag(
+    drivables: Query<(Entity, &Velocity, &Transform, &VehicleConfig), With<Drivable>>,
+    mut add_forces: EventWriter<AddForce>,
 ) {
-    for (mut tire_transform, tire, parent) in &mut tires {
-        let parent_config = drivables.get(parent.get()).unwrap();
-        let mut multiplier = if keys.pressed(KeyCode::D) {
-            -1.0
-        } else if keys.pressed(KeyCode::A) {
-            1.0
+    for (entity, velocity, transform, config) in &drivables {
+        add_forces.send(AddForce {
+            force: -config.resistance.drag * velocity.linvel.length() * velocity.linvel,
+            point: transform.translation,
+            entity,
+        });
+    }
+}
+
+/// Moves `current` towards `target` by at most `max_delta`.
+fn move_towards(current: f32, target: f32, max_delta: f32) -> f32 {
+    current + (target - current).clamp(-max_delta, max_delta)
+}
+
+/// Turns each drivable's steering towards where the player is steering, no faster than its
+/// steering rate, so a key press ramps the wheels over rather than snapping them to full lock.
+fn steer_vehicles(
+    time: Res<Time>,
+    vehicle_input: Res<VehicleInput>,
+    mut drivables: Query<(&VehicleConfig, &Velocity, &mut SteeringAngle), With<Drivable>>,
+) {
+    let delta = time.delta_seconds();
+    for (config, velocity, mut steering_angle) in &mut drivables {
+        let speed_ratio = if config.max_speed > 0.0 {
+            (velocity.linvel.length() / config.max_speed).min(1.0)
         } else {
             0.0
         };
+        let lock =
+            config.turn_radius * (1.0 + (config.steering.high_speed_lock - 1.0) * speed_ratio);
+        let (target, rate) = if vehicle_input.steer == 0.0 {
+            (0.0, config.steering.centering_rate)
+        } else {
+            (-vehicle_input.steer * lock, config.steering.rate)
+        };
+        steering_angle.0 = move_towards(steering_angle.0, target, rate * delta);
+    }
+}
 
-        for gamepad in gamepads.iter() {
-            let axis_lx = GamepadAxis {
-                gamepad,
-                axis_type: GamepadAxisType::LeftStickX,
-            };
-            if let Some(x) = axes.get(axis_lx) {
-                if x != 0.0 {
-                    multiplier = -x;
-                }
-            }
-        }
+/// Angle a wheel has to turn to roll around the same point as an imaginary wheel on the
+/// vehicle's center line turned by `steering_angle`.
+///
+/// `wheelbase` is how far the wheel is in front of the turning center and `lateral` is how far
+/// it is to the right of the center line.
+fn ackermann_angle(steering_angle: f32, wheelbase: f32, lateral: f32) -> f32 {
+    let tan = steering_angle.tan();
+    // the turning center is wheelbase / tan to the left, so wheels on the inside of the turn are
+    // closer to it and turn further
+    let denominator = wheelbase + lateral * tan;
+    if wheelbase.abs() < f32::EPSILON || denominator.abs() < f32::EPSILON {
+        return steering_angle;
+    }
+    (wheelbase * tan / denominator).atan()
+}
 
-        if tire.turns {
-            tire_transform.rotation =
-                Quat::from_axis_angle(Vec3::Y, multiplier * parent_config.turn_radius);
+fn turn_tires(
+    drivables: Query<(&SteeringAngle, &VehicleConfig), With<Drivable>>,
+    mut tires: Query<(&mut Transform, &Tire, &Parent)>,
+) {
+    for (mut tire_transform, tire, parent) in &mut tires {
+        let (steering_angle, config) = drivables.get(parent.get()).unwrap();
+        if !tire.turns {
+            continue;
         }
+        let angle = match config.turning_center() {
+            Some(turning_center) => {
+                let ackermann = ackermann_angle(
+                    steering_angle.0,
+                    tire_transform.translation.x - turning_center,
+                    tire_transform.translation.z,
+                );
+                steering_angle.0 + (ackermann - steering_angle.0) * config.steering.ackermann
+            }
+            None => steering_angle.0,
+        };
+        tire_transform.rotation = Quat::from_axis_angle(Vec3::Y, angle);
     }
 }
 
-fn calculate_tire_turning_forces(
-    drivables: Query<(Entity, &Transform, &Velocity, &ReadMassProperties), With<Drivable>>,
-    tires: Query<(&Tire, &GlobalTransform, &Parent)>,
+/// Pacejka's magic formula: how much of its peak grip a tire makes at the given slip.
+fn magic_formula(tire: &Tire, slip: f32) -> f32 {
+    let b_slip = tire.stiffness * slip;
+    (tire.shape * (b_slip - tire.curvature * (b_slip - b_slip.atan())).atan()).sin()
+}
+
+/// Works out how much each grounded tire is slipping, along and across the way it points, and
+/// pushes the drivable with the grip that makes.
+///
+/// Slip along the tire is between the tread and the road, so each tire keeps track of how fast
+/// its tread is moving, spun up by the engine, slowed by the brakes and dragged along by the road.
+/// Slip across the tire is its slip angle. The two are combined so a tire spinning or locked up
+/// has less grip left for cornering, and the whole force never goes past the friction circle of
+/// `grip` times the load on the tire.
+fn calculate_tire_grip_forces(
+    time: Res<Time>,
+    drivables: Query<(Entity, &Transform, &Velocity), With<Drivable>>,
+    mut tires: Query<(&mut Tire, &GlobalTransform, &Parent)>,
     mut add_forces: EventWriter<AddForce>,
 ) {
-    for (tire, tire_transform, parent) in &tires {
-        let (parent_entity, parent_transform, parent_velocity, ReadMassProperties(mass_properties)) =
+    let delta = time.delta_seconds().max(f32::EPSILON);
+    for (mut tire, tire_transform, parent) in &mut tires {
+        let (parent_entity, parent_transform, parent_velocity) =
             drivables.get(parent.get()).unwrap();
-        if tire.distance_to_ground.is_some() {
-            let steering_direction = tire_transform.compute_transform().forward();
-            let tire_velocity = parent_velocity.linear_velocity_at_point(
-                tire_transform.translation(),
-                parent_transform.translation,
-            );
-            let steering_velocity = steering_direction.dot(tire_velocity);
-            let desired_velocity_change = -steering_velocity * tire.grip;
-            let desired_acceleration = desired_velocity_change * 60.0;
-            add_forces.send(AddForce {
-                force: steering_direction * desired_acceleration * (mass_properties.mass / 4.0),
-                point: tire_transform.translation(),
-                entity: parent_entity,
-            });
+        let tire_rotation = tire_transform.compute_transform().rotation;
+        let tire_forward = tire_rotation * Vec3::X;
+        let tire_side = tire_rotation * Vec3::Z;
+        let tire_velocity = parent_velocity
+            .linear_velocity_at_point(tire_transform.translation(), parent_transform.translation);
+        let forward_speed = tire_velocity.dot(tire_forward);
+        let side_speed = tire_velocity.dot(tire_side);
+
+        let drive_force = tire.drive_torque / TIRE_RADIUS;
+        let max_brake_change = delta * tire.brake_torque / TIRE_RADIUS / WHEEL_MASS;
+        let free_wheel_speed = tire.wheel_speed + delta * drive_force / WHEEL_MASS;
+        if tire.distance_to_ground.is_none() || tire.load <= 0.0 {
+            tire.wheel_speed = move_towards(free_wheel_speed, 0.0, max_brake_change);
+            continue;
         }
+
+        // slip is measured against at least a walking pace, so it doesn't blow up as the tire
+        // stops
+        let reference_speed = forward_speed.abs().max(TIRE_SLIP_REFERENCE_SPEED);
+        let peak_force = tire.grip * tire.load;
+        // the road drags the tread towards the ground speed, solved implicitly so stiff tires
+        // don't make the wheel speed oscillate
+        let road_stiffness =
+            delta * peak_force * tire.stiffness * tire.shape / reference_speed / WHEEL_MASS;
+        let wheel_speed =
+            (free_wheel_speed + road_stiffness * forward_speed) / (1.0 + road_stiffness);
+        tire.wheel_speed = move_towards(wheel_speed, 0.0, max_brake_change);
+
+        let slip_ratio = (tire.wheel_speed - forward_speed) / reference_speed;
+        // the tangent of the slip angle
+        let side_slip = side_speed / reference_speed;
+        let slip = Vec2::new(slip_ratio, side_slip);
+        let slip_amount = slip.length();
+        if slip_amount <= f32::EPSILON {
+            continue;
+        }
+        let grip_force = slip / slip_amount * peak_force * magic_formula(&tire, slip_amount);
+
+        // never push harder than it takes to stop the slip this frame, or the tire overshoots
+        // and jitters at low speed
+        let tire_mass = tire.load / 9.81;
+        let forward_force = grip_force.x.clamp(
+            -tire_mass * (tire.wheel_speed - forward_speed).abs() / delta,
+            tire_mass * (tire.wheel_speed - forward_speed).abs() / delta,
+        );
+        let side_force = (-grip_force.y).clamp(
+            -tire_mass * side_speed.abs() / delta,
+            tire_mass * side_speed.abs() / delta,
+        );
+        add_forces.send(AddForce {
+            force: forward_force * tire_forward + side_force * tire_side,
+            point: tire_transform.translation(),
+            entity: parent_entity,
+        });
     }
 }
 
@@ -655,7 +1112,7 @@ fn sum_all_forces(
         entity,
     } in add_forces.iter()
     {
-        let (_, transform, _) = drivables.get(entity.clone()).unwrap();
+        let (_, transform, _) = drivables.get(*entity).unwrap();
         let force_on_entity = ExternalForce::at_point(*force, *point, transform.translation);
         *final_forces.get_mut(entity).unwrap() += force_on_entity;
     }
@@ -697,3 +1154,119 @@ fn move_tires_to_ground(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inside_wheel_turns_further_than_outside() {
+        for steering_angle in [0.3, -0.3] {
+            // turning left, with a positive angle, puts the left wheel on the inside
+            let (inside, outside) = if steering_angle > 0.0 {
+                (-0.8, 0.8)
+            } else {
+                (0.8, -0.8)
+            };
+            let inside_angle = ackermann_angle(steering_angle, 2.5, inside);
+            let outside_angle = ackermann_angle(steering_angle, 2.5, outside);
+            assert!(inside_angle.abs() > steering_angle.abs());
+            assert!(outside_angle.abs() < steering_angle.abs());
+            assert_eq!(inside_angle.signum(), steering_angle.signum());
+            assert_eq!(outside_angle.signum(), steering_angle.signum());
+        }
+    }
+
+    #[test]
+    fn center_line_wheel_turns_by_the_steering_angle() {
+        assert!((ackermann_angle(0.4, 2.5, 0.0) - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn straight_ahead_stays_straight() {
+        assert_eq!(ackermann_angle(0.0, 2.5, 0.8), 0.0);
+        assert_eq!(ackermann_angle(0.0, 2.5, -0.8), 0.0);
+    }
+
+    #[test]
+    fn zero_wheelbase_falls_back_to_the_steering_angle() {
+        assert_eq!(ackermann_angle(0.3, 0.0, 0.8), 0.3);
+    }
+
+    #[test]
+    fn magic_formula_is_zero_without_slip() {
+        assert_eq!(magic_formula(&Tire::default(), 0.0), 0.0);
+    }
+
+    /// Slip at which the tire makes the most grip, searched for on a fine grid.
+    fn peak_slip(tire: &Tire) -> f32 {
+        (1..=100_000)
+            .map(|i| i as f32 / 10_000.0)
+            .max_by(|a, b| magic_formula(tire, *a).total_cmp(&magic_formula(tire, *b)))
+            .unwrap()
+    }
+
+    #[test]
+    fn peak_slip_follows_stiffness_and_shape() {
+        // without curvature the peak is where shape * atan(stiffness * slip) is a right angle
+        for shape in [1.2, 1.5, 1.9] {
+            let tire = Tire {
+                shape,
+                curvature: 0.0,
+                ..default()
+            };
+            let expected = (PI / 2.0 / shape).tan() / tire.stiffness;
+            let peak = peak_slip(&tire);
+            assert!(
+                (peak - expected).abs() < 0.001,
+                "shape {} peaked at {} instead of {}",
+                shape,
+                peak,
+                expected
+            );
+            assert!((magic_formula(&tire, peak) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn curvature_pushes_the_peak_to_more_slip() {
+        let peak_at = |curvature| {
+            peak_slip(&Tire {
+                curvature,
+                ..default()
+            })
+        };
+        assert!(peak_at(-0.5) < peak_at(0.0));
+        assert!(peak_at(0.0) < peak_at(0.6));
+        assert!(peak_at(0.6) < peak_at(0.9));
+    }
+
+    #[test]
+    fn grip_falls_off_past_the_peak_to_the_sliding_value() {
+        let tire = Tire::default();
+        let peak = peak_slip(&tire);
+        let mut previous = magic_formula(&tire, peak);
+        for i in 1..=100 {
+            let grip = magic_formula(&tire, peak * (1.0 + i as f32 * 0.5));
+            assert!(grip < previous, "grip went back up past the peak");
+            previous = grip;
+        }
+        // sliding, the formula tends to sin(shape * pi / 2)
+        let sliding = (tire.shape * PI / 2.0).sin();
+        assert!((magic_formula(&tire, 1000.0) - sliding).abs() < 0.01);
+        assert!(sliding < 0.75);
+    }
+
+    #[test]
+    fn magic_formula_builds_up_then_peaks_near_full_grip() {
+        let tire = Tire::default();
+        let small = magic_formula(&tire, 0.01);
+        let peak = (1..=100)
+            .map(|i| magic_formula(&tire, i as f32 / 100.0))
+            .fold(0.0, f32::max);
+        assert!(small > 0.0 && small < peak);
+        assert!(peak > 0.99);
+        // grip is odd in slip, so the force always opposes it
+        assert!((magic_formula(&tire, -0.2) + magic_formula(&tire, 0.2)).abs() < 1e-6);
+    }
+}
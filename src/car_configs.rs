@@ -1,100 +1,302 @@
-use bevy::prelude::*;
-
-use crate::car::VehicleConfig;
-
-const CAR_LENGTH: f32 = 5.31114 / 2.0;
-#[allow(dead_code)]
-pub const CAR_CONFIG: VehicleConfig = VehicleConfig {
-    height: 1.452024 / 2.0,
-    width: 2.02946 / 2.0,
-    length: CAR_LENGTH,
-    wheelbase: 3.61912 / 2.0,
-    wheel_offset: 0.2,
-    spring_offset: 1.252926,
-    spring_power: 300.0,
-    shock: 45.0,
-    max_speed: 50.0,
-    max_force: 100.0,
-    turn_radius: 0.45811518324607,
-    anchor_point: Vec3 {
-        x: -CAR_LENGTH - 0.787,
-        y: -0.7,
-        z: 0.0,
-    },
-    scale: 1.0,
-    starting_tire_grip: 0.7,
-};
+use std::f32::consts::PI;
 
-const TRAILER_LENGTH: f32 = 7.8768 / 2.0;
-const TRAILER_WIDTH: f32 = 2.159 / 2.0;
-#[allow(dead_code)]
-pub const TRAILER_CONFIG: VehicleConfig = VehicleConfig {
-    height: 0.18234 / 2.0,
-    width: TRAILER_WIDTH,
-    length: TRAILER_LENGTH,
-    wheelbase: 1.0 / 2.0,
-    wheel_offset: -1.0,
-    spring_offset: 1.0,
-    spring_power: 21.0,
-    shock: 5.0,
-    max_speed: 0.0,
-    max_force: 0.0,
-    turn_radius: 0.0,
-    anchor_point: Vec3 {
-        x: TRAILER_LENGTH + TRAILER_WIDTH,
-        y: -(0.18234 / 2.0),
-        z: 0.0,
-    },
-    scale: 1.0,
-    starting_tire_grip: 0.7,
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::{BoxedFuture, HashMap, HashSet},
 };
+use bevy_rapier3d::prelude::*;
 
-#[allow(dead_code)]
-const DRIFTER_LENGTH: f32 = 3.31114 / 2.0;
-#[allow(dead_code)]
-pub const DRIFTER_CONFIG: VehicleConfig = VehicleConfig {
-    height: 1.252024 / 2.0,
-    width: 2.02946 / 2.0,
-    length: DRIFTER_LENGTH,
-    wheelbase: 3.11912 / 2.0,
-    wheel_offset: 0.0,
-    spring_offset: 1.252926,
-    spring_power: 300.0,
-    shock: 45.0,
-    max_speed: 50.0,
-    max_force: 160.0,
-    turn_radius: 0.45811518324607,
-    anchor_point: Vec3 {
-        x: -DRIFTER_LENGTH * 1.1,
-        y: -0.7,
-        z: 0.0,
-    },
-    scale: 1.0,
-    starting_tire_grip: 0.03,
+use crate::{
+    car::VehicleConfig,
+    hitch::{self, Hitch, UncoupleVehicles},
 };
 
-#[allow(dead_code)]
-const DRIFTER_TRAILER_LENGTH: f32 = 2.8768 / 2.0;
-#[allow(dead_code)]
-const DRIFTER_TRAILER_WIDTH: f32 = 2.159 / 2.0;
-#[allow(dead_code)]
-pub const DRIFTER_TRAILER_CONFIG: VehicleConfig = VehicleConfig {
-    height: 0.18234 / 2.0,
-    width: DRIFTER_TRAILER_WIDTH,
-    length: DRIFTER_TRAILER_LENGTH,
-    wheelbase: 1.0 / 2.0,
-    wheel_offset: 0.0,
-    spring_offset: 1.0,
-    spring_power: 15.0,
-    shock: 3.0,
-    max_speed: 0.0,
-    max_force: 0.0,
-    turn_radius: 0.0,
-    anchor_point: Vec3 {
-        x: DRIFTER_TRAILER_LENGTH + DRIFTER_TRAILER_WIDTH,
-        y: -(0.18234 / 2.0),
-        z: 0.0,
-    },
-    scale: 1.0,
-    starting_tire_grip: 0.03,
-};
+/// Names of the vehicle definitions under `assets/vehicles/`, loaded as `<name>.vehicle.ron`.
+///
+/// These are listed explicitly because folder loading isn't supported on the web build.
+const VEHICLE_NAMES: [&str; 5] = ["car", "trailer", "dolly", "drifter", "drifter_trailer"];
+
+pub struct CarConfigsPlugin;
+
+impl Plugin for CarConfigsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<VehicleConfig>()
+            .init_asset_loader::<VehicleConfigLoader>()
+            .init_resource::<VehicleConfigs>()
+            .add_systems(Startup, load_vehicle_configs)
+            .add_systems(Update, hot_reload_vehicle_configs);
+    }
+}
+
+/// Handles to every vehicle definition, keyed by file name.
+#[derive(Resource, Default)]
+pub struct VehicleConfigs(HashMap<String, Handle<VehicleConfig>>);
+
+impl VehicleConfigs {
+    pub fn handle(&self, name: &str) -> Option<&Handle<VehicleConfig>> {
+        self.0.get(name)
+    }
+
+    /// Returns the config with the given name, or `None` if it doesn't exist or hasn't loaded yet.
+    pub fn get<'a>(
+        &self,
+        name: &str,
+        assets: &'a Assets<VehicleConfig>,
+    ) -> Option<&'a VehicleConfig> {
+        self.handle(name).and_then(|handle| assets.get(handle))
+    }
+}
+
+#[derive(Default)]
+struct VehicleConfigLoader;
+
+impl AssetLoader for VehicleConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let vehicle_config: VehicleConfig = ron::de::from_bytes(bytes)?;
+            if let Err(message) = vehicle_config.validate() {
+                return Err(bevy::asset::Error::msg(format!(
+                    "invalid vehicle config {:?}: {}",
+                    load_context.path(),
+                    message
+                )));
+            }
+            load_context.set_default_asset(LoadedAsset::new(vehicle_config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vehicle.ron"]
+    }
+}
+
+impl VehicleConfig {
+    /// Checks that the config describes a vehicle that can actually be spawned and simulated.
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("height", self.height),
+            ("width", self.width),
+            ("length", self.length),
+            ("scale", self.scale),
+        ];
+        for (field, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{} must be greater than 0, got {}", field, value));
+            }
+        }
+
+        let non_negative = [
+            ("spring_offset", self.spring_offset),
+            ("spring_power", self.spring_power),
+            ("shock", self.shock),
+            ("max_speed", self.max_speed),
+            ("turn_radius", self.turn_radius),
+        ];
+        for (field, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} must not be negative, got {}", field, value));
+            }
+        }
+
+        for (field, hitch) in [
+            ("front_hitch", self.front_hitch),
+            ("rear_hitch", self.rear_hitch),
+        ] {
+            if hitch.is_some_and(|anchor| !anchor.is_finite()) {
+                return Err(format!("{} must be finite", field));
+            }
+        }
+        let hitch_limits = [
+            ("hitch max_yaw", self.hitch.max_yaw),
+            ("hitch max_pitch", self.hitch.max_pitch),
+            ("hitch max_roll", self.hitch.max_roll),
+            ("hitch jackknife_angle", self.hitch.jackknife_angle),
+        ];
+        for (field, value) in hitch_limits {
+            if !(0.0..=PI).contains(&value) {
+                return Err(format!("{} must be between 0 and pi, got {}", field, value));
+            }
+        }
+        let steering_rates = [
+            ("steering rate", self.steering.rate),
+            ("steering centering_rate", self.steering.centering_rate),
+        ];
+        for (field, value) in steering_rates {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{} must be greater than 0, got {}", field, value));
+            }
+        }
+        let drivetrain = &self.drivetrain;
+        let drivetrain_positive = [
+            ("drivetrain idle_rpm", drivetrain.idle_rpm),
+            ("drivetrain reverse_ratio", drivetrain.reverse_ratio),
+            ("drivetrain final_drive", drivetrain.final_drive),
+        ];
+        for (field, value) in drivetrain_positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{} must be greater than 0, got {}", field, value));
+            }
+        }
+        if !(drivetrain.max_torque.is_finite() && drivetrain.max_torque >= 0.0) {
+            return Err(format!(
+                "drivetrain max_torque must not be negative, got {}",
+                drivetrain.max_torque
+            ));
+        }
+        if !(drivetrain.idle_rpm < drivetrain.shift_down_rpm
+            && drivetrain.shift_down_rpm < drivetrain.shift_up_rpm
+            && drivetrain.shift_up_rpm <= drivetrain.redline_rpm)
+        {
+            return Err(
+                "drivetrain rpms must go idle_rpm < shift_down_rpm < shift_up_rpm <= redline_rpm"
+                    .to_string(),
+            );
+        }
+        drivetrain.power_curve.validate()?;
+        if drivetrain.gear_ratios.is_empty() {
+            return Err("drivetrain needs at least one forward gear".to_string());
+        }
+        if let Some(ratio) = drivetrain
+            .gear_ratios
+            .iter()
+            .find(|ratio| !(ratio.is_finite() && **ratio > 0.0))
+        {
+            return Err(format!(
+                "drivetrain gear_ratios must be greater than 0, got {}",
+                ratio
+            ));
+        }
+        let brakes_and_resistance = [
+            ("brakes max_torque", self.brakes.max_torque),
+            ("brakes handbrake_torque", self.brakes.handbrake_torque),
+            ("resistance rolling", self.resistance.rolling),
+            ("resistance drag", self.resistance.drag),
+        ];
+        for (field, value) in brakes_and_resistance {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} must not be negative, got {}", field, value));
+            }
+        }
+        let fractions = [
+            ("steering high_speed_lock", self.steering.high_speed_lock),
+            ("steering ackermann", self.steering.ackermann),
+            ("brakes front_bias", self.brakes.front_bias),
+        ];
+        for (field, value) in fractions {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{} must be between 0 and 1, got {}", field, value));
+            }
+        }
+        if self.hitch.damping.is_some_and(|damping| damping < 0.0) {
+            return Err("hitch damping must not be negative".to_string());
+        }
+        if self.axles.is_empty() {
+            return Err("a vehicle needs at least one axle".to_string());
+        }
+        for (i, axle) in self.axles.iter().enumerate() {
+            if !axle.position.is_finite() {
+                return Err(format!("axle {} position must be finite", i + 1));
+            }
+            if !(axle.track_width.is_finite() && axle.track_width >= 0.0) {
+                return Err(format!(
+                    "axle {} track_width must not be negative, got {}",
+                    i + 1,
+                    axle.track_width
+                ));
+            }
+        }
+        if !(0.0..=2.0).contains(&self.starting_tire_grip) {
+            return Err(format!(
+                "starting_tire_grip must be between 0 and 2, got {}",
+                self.starting_tire_grip
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn load_vehicle_configs(
+    asset_server: Res<AssetServer>,
+    mut vehicle_configs: ResMut<VehicleConfigs>,
+) {
+    for name in VEHICLE_NAMES {
+        vehicle_configs.0.insert(
+            name.to_string(),
+            asset_server.load(format!("vehicles/{}.vehicle.ron", name)),
+        );
+    }
+}
+
+/// Copies an edited vehicle definition onto every vehicle that was spawned from it.
+///
+/// Hitch anchors and limits are copied too, and the joint of every coupling they touch is rebuilt
+/// so the change shows up without re-hitching. A coupling whose anchor was removed is uncoupled.
+fn hot_reload_vehicle_configs(
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<VehicleConfig>>,
+    mut uncouple_events: EventWriter<UncoupleVehicles>,
+    vehicle_config_assets: Res<Assets<VehicleConfig>>,
+    mut vehicles: Query<(Entity, &Handle<VehicleConfig>, &mut VehicleConfig)>,
+    mut hitches: Query<&mut Hitch>,
+) {
+    let mut rehitched = HashSet::new();
+    for event in asset_events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        let Some(new_config) = vehicle_config_assets.get(handle) else {
+            continue;
+        };
+        for (vehicle_entity, vehicle_handle, mut vehicle_config) in &mut vehicles {
+            if vehicle_handle != handle {
+                continue;
+            }
+            info!("reloaded vehicle config for {:?}", vehicle_entity);
+            if new_config.axles != vehicle_config.axles {
+                warn!("axle changes only take effect when the vehicle is next spawned");
+            }
+            *vehicle_config = new_config.clone();
+            commands.entity(vehicle_entity).insert(Collider::cuboid(
+                new_config.length,
+                new_config.height,
+                new_config.width,
+            ));
+            if let Ok(mut hitch) = hitches.get_mut(vehicle_entity) {
+                hitch.front_anchor = new_config.front_hitch;
+                hitch.rear_anchor = new_config.rear_hitch;
+                hitch.config = new_config.hitch.clone();
+                if hitch.towed_by.is_some() {
+                    rehitched.insert(vehicle_entity);
+                }
+                rehitched.extend(hitch.towing);
+            }
+        }
+    }
+
+    // Joints live on the rear vehicle, so they're rebuilt once both ends have their new anchors.
+    for rear in rehitched {
+        let Ok(rear_hitch) = hitches.get(rear) else {
+            continue;
+        };
+        let Some(front) = rear_hitch.towed_by else {
+            continue;
+        };
+        let front_anchor = hitches.get(front).ok().and_then(|hitch| hitch.rear_anchor);
+        let (Some(front_anchor), Some(rear_anchor)) = (front_anchor, rear_hitch.front_anchor)
+        else {
+            warn!("{:?} lost its hitch anchor on reload, uncoupling it", rear);
+            uncouple_events.send(UncoupleVehicles { rear });
+            continue;
+        };
+        commands.entity(rear).insert(ImpulseJoint::new(
+            front,
+            hitch::hitch_joint(front_anchor, rear_anchor, &rear_hitch.config),
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    car::{Car, Drivable, ResetRig, RigPose, SpawnPoint, VehiclePose},
+    game_state::GameState,
+    hitch::Hitch,
+};
+
+pub struct CheckpointPlugin;
+
+impl Plugin for CheckpointPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Checkpoint>()
+            .init_resource::<LastCheckpoint>()
+            .add_systems(
+                Update,
+                (
+                    clear_last_checkpoint,
+                    record_checkpoints.run_if(in_state(GameState::Playing)),
+                    draw_checkpoints,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// A trigger that remembers where the rig was when the car drove into it.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Checkpoint;
+
+/// The checkpoint the car last drove into this attempt, and the rig's pose at the time.
+#[derive(Resource, Default)]
+pub struct LastCheckpoint {
+    pub checkpoint: Option<Entity>,
+    pub pose: Option<RigPose>,
+}
+
+pub fn spawn_checkpoint(commands: &mut Commands, name: &str, transform: Transform) -> Entity {
+    commands
+        .spawn((
+            TransformBundle {
+                local: transform,
+                ..default()
+            },
+            Checkpoint,
+            Collider::cuboid(0.5, 0.5, 0.5),
+            Sensor,
+            Name::from(name),
+        ))
+        .id()
+}
+
+/// Forgets the last checkpoint on a full restart, or once the level it was in is gone.
+fn clear_last_checkpoint(
+    mut reset_events: EventReader<ResetRig>,
+    checkpoints: Query<(), With<Checkpoint>>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+) {
+    let despawned = last_checkpoint
+        .checkpoint
+        .is_some_and(|checkpoint| !checkpoints.contains(checkpoint));
+    if reset_events.iter().count() > 0 || despawned {
+        *last_checkpoint = LastCheckpoint::default();
+    }
+}
+
+/// Records where every vehicle is, and what it's hitched to, as the car enters a checkpoint.
+/// Staying inside doesn't keep recording, so backing out of a checkpoint doesn't move it.
+fn record_checkpoints(
+    rapier_context: Res<RapierContext>,
+    checkpoints: Query<(Entity, &Name), With<Checkpoint>>,
+    car: Query<Entity, With<Car>>,
+    spawn_points: Query<&SpawnPoint>,
+    drivables: Query<(&Transform, &Hitch), With<Drivable>>,
+    mut last_checkpoint: ResMut<LastCheckpoint>,
+    mut inside: Local<Option<Entity>>,
+) {
+    let (Ok(car), Ok(spawn_point)) = (car.get_single(), spawn_points.get_single()) else {
+        return;
+    };
+    let entered = checkpoints
+        .iter()
+        .find(|(checkpoint, _)| rapier_context.intersection_pair(*checkpoint, car) == Some(true));
+    let now_inside = entered.map(|(checkpoint, _)| checkpoint);
+    if now_inside == *inside {
+        return;
+    }
+    *inside = now_inside;
+    let Some((checkpoint, name)) = entered else {
+        return;
+    };
+
+    let pose = spawn_point
+        .all_vehicles()
+        .filter_map(|vehicle| {
+            let (transform, hitch) = drivables.get(vehicle).ok()?;
+            Some(VehiclePose {
+                vehicle,
+                transform: *transform,
+                towed_by: hitch.towed_by,
+            })
+        })
+        .collect();
+    info!("reached checkpoint {}", name);
+    *last_checkpoint = LastCheckpoint {
+        checkpoint: Some(checkpoint),
+        pose: Some(RigPose(pose)),
+    };
+}
+
+fn draw_checkpoints(
+    mut gizmos: Gizmos,
+    checkpoints: Query<(Entity, &GlobalTransform), With<Checkpoint>>,
+    last_checkpoint: Res<LastCheckpoint>,
+) {
+    for (checkpoint, checkpoint_transform) in &checkpoints {
+        let color = if last_checkpoint.checkpoint == Some(checkpoint) {
+            Color::GREEN
+        } else {
+            Color::ORANGE
+        };
+        gizmos.cuboid(checkpoint_transform.compute_transform(), color);
+    }
+}
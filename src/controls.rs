@@ -0,0 +1,308 @@
+use std::fmt;
+
+use bevy::{input::InputSystem, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+/// Where the player's bindings are saved, see `storage`.
+const CONTROLS_KEY: &str = "controls.ron";
+
+/// How far an analog input has to be pushed to count as pressing its action.
+const PRESS_THRESHOLD: f32 = 0.5;
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_bindings())
+            .init_resource::<Input<Action>>()
+            .init_resource::<VehicleInput>()
+            .add_systems(PreUpdate, read_actions.after(InputSystem));
+    }
+}
+
+/// Something the player can do, independent of the key or button it's bound to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Throttle,
+    Brake,
+    SteerLeft,
+    SteerRight,
+    Handbrake,
+    ShiftUp,
+    ShiftDown,
+    ResetToCheckpoint,
+    Restart,
+    ToggleHitch,
+    Pause,
+    ToggleForceGizmos,
+    ToggleSettings,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::Throttle,
+        Action::Brake,
+        Action::SteerLeft,
+        Action::SteerRight,
+        Action::Handbrake,
+        Action::ShiftUp,
+        Action::ShiftDown,
+        Action::ResetToCheckpoint,
+        Action::Restart,
+        Action::ToggleHitch,
+        Action::Pause,
+        Action::ToggleForceGizmos,
+        Action::ToggleSettings,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Throttle => "Throttle",
+            Action::Brake => "Brake",
+            Action::SteerLeft => "Steer left",
+            Action::SteerRight => "Steer right",
+            Action::Handbrake => "Handbrake",
+            Action::ShiftUp => "Shift up",
+            Action::ShiftDown => "Shift down",
+            Action::ResetToCheckpoint => "Reset to checkpoint",
+            Action::Restart => "Restart",
+            Action::ToggleHitch => "Hitch / unhitch",
+            Action::Pause => "Pause",
+            Action::ToggleForceGizmos => "Show tire forces",
+            Action::ToggleSettings => "Show settings",
+        }
+    }
+
+    fn default_bindings(&self) -> Vec<Binding> {
+        use Binding::*;
+        match self {
+            Action::Throttle => vec![Key(KeyCode::W), Button(GamepadButtonType::RightTrigger2)],
+            Action::Brake => vec![Key(KeyCode::S), Button(GamepadButtonType::LeftTrigger2)],
+            Action::SteerLeft => vec![
+                Key(KeyCode::A),
+                Stick {
+                    axis: GamepadAxisType::LeftStickX,
+                    positive: false,
+                },
+            ],
+            Action::SteerRight => vec![
+                Key(KeyCode::D),
+                Stick {
+                    axis: GamepadAxisType::LeftStickX,
+                    positive: true,
+                },
+            ],
+            Action::Handbrake => vec![Key(KeyCode::Space), Button(GamepadButtonType::East)],
+            Action::ShiftUp => vec![Key(KeyCode::E), Button(GamepadButtonType::RightTrigger)],
+            Action::ShiftDown => vec![Key(KeyCode::Q), Button(GamepadButtonType::LeftTrigger)],
+            Action::ResetToCheckpoint => vec![Key(KeyCode::R), Button(GamepadButtonType::Start)],
+            Action::Restart => vec![Key(KeyCode::Back), Button(GamepadButtonType::North)],
+            Action::ToggleHitch => vec![Key(KeyCode::H), Button(GamepadButtonType::West)],
+            Action::Pause => vec![Key(KeyCode::P), Button(GamepadButtonType::Select)],
+            Action::ToggleForceGizmos => vec![Key(KeyCode::L)],
+            Action::ToggleSettings => vec![Key(KeyCode::Escape)],
+        }
+    }
+}
+
+/// A key, button or stick direction that triggers an action. Gamepad bindings listen to every
+/// connected gamepad.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+    /// One direction of a stick axis.
+    Stick {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Button(button) => write!(f, "Gamepad {:?}", button),
+            Binding::Stick { axis, positive } => {
+                write!(
+                    f,
+                    "Gamepad {:?} {}",
+                    axis,
+                    if *positive { "+" } else { "-" }
+                )
+            }
+        }
+    }
+}
+
+/// What each action is bound to.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct ControlBindings(pub HashMap<Action, Vec<Binding>>);
+
+impl Default for ControlBindings {
+    fn default() -> Self {
+        ControlBindings(
+            Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_bindings()))
+                .collect(),
+        )
+    }
+}
+
+impl ControlBindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// The driving controls for this frame, gathered from every binding.
+#[derive(Resource, Default)]
+pub struct VehicleInput {
+    /// How far the accelerator is pressed, from 0 to 1.
+    pub throttle: f32,
+    /// How far the brake is pressed, from 0 to 1.
+    pub brake: f32,
+    /// From -1 for full left lock to 1 for full right lock.
+    pub steer: f32,
+    pub handbrake: bool,
+    /// Whether the gear selector should move up, towards drive, this frame.
+    pub shift_up: bool,
+    /// Whether the gear selector should move down, towards reverse, this frame.
+    pub shift_down: bool,
+    /// Whether the rig should be put back at the last checkpoint this frame.
+    pub reset: bool,
+    /// Whether the attempt should start over this frame.
+    pub restart: bool,
+}
+
+fn load_bindings() -> ControlBindings {
+    let Some(contents) = storage::read_saved(CONTROLS_KEY) else {
+        return ControlBindings::default();
+    };
+    match ron::from_str::<ControlBindings>(&contents) {
+        Ok(mut bindings) => {
+            // actions added since the bindings were saved get their defaults
+            for action in Action::ALL {
+                bindings
+                    .0
+                    .entry(action)
+                    .or_insert_with(|| action.default_bindings());
+            }
+            bindings
+        }
+        Err(e) => {
+            warn!("couldn't read saved controls, using the defaults: {}", e);
+            ControlBindings::default()
+        }
+    }
+}
+
+pub fn save_bindings(bindings: &ControlBindings) {
+    let result = ron::ser::to_string_pretty(bindings, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())
+        .and_then(|contents| storage::write_saved(CONTROLS_KEY, &contents));
+    if let Err(e) = result {
+        warn!("couldn't save controls: {}", e);
+    }
+}
+
+/// How far a binding is pressed, from 0 to 1, on whichever gamepad is pressing it the most.
+fn binding_value(
+    binding: &Binding,
+    keys: &Input<KeyCode>,
+    button_axes: &Axis<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+    gamepads: &Gamepads,
+) -> f32 {
+    match *binding {
+        Binding::Key(key) => {
+            if keys.pressed(key) {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        Binding::Button(button_type) => gamepads
+            .iter()
+            .filter_map(|gamepad| button_axes.get(GamepadButton::new(gamepad, button_type)))
+            .fold(0.0, f32::max),
+        Binding::Stick { axis, positive } => gamepads
+            .iter()
+            .filter_map(|gamepad| axes.get(GamepadAxis::new(gamepad, axis)))
+            .map(|value| if positive { value } else { -value })
+            .fold(0.0, f32::max),
+    }
+}
+
+/// Turns raw keyboard and gamepad input into actions, and the driving actions into
+/// `VehicleInput`.
+fn read_actions(
+    bindings: Res<ControlBindings>,
+    keys: Res<Input<KeyCode>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut actions: ResMut<Input<Action>>,
+    mut vehicle_input: ResMut<VehicleInput>,
+) {
+    actions.clear();
+    let mut values = HashMap::new();
+    for action in Action::ALL {
+        let value = bindings
+            .get(action)
+            .iter()
+            .map(|binding| binding_value(binding, &keys, &button_axes, &axes, &gamepads))
+            .fold(0.0, f32::max)
+            .min(1.0);
+        if value >= PRESS_THRESHOLD {
+            actions.press(action);
+        } else {
+            actions.release(action);
+        }
+        values.insert(action, value);
+    }
+
+    *vehicle_input = VehicleInput {
+        throttle: values[&Action::Throttle],
+        brake: values[&Action::Brake],
+        steer: values[&Action::SteerRight] - values[&Action::SteerLeft],
+        handbrake: actions.pressed(Action::Handbrake),
+        shift_up: actions.just_pressed(Action::ShiftUp),
+        shift_down: actions.just_pressed(Action::ShiftDown),
+        reset: actions.just_pressed(Action::ResetToCheckpoint),
+        restart: actions.just_pressed(Action::Restart),
+    };
+}
+
+/// The first key, button or stick direction pressed this frame, for binding it to an action.
+pub fn pressed_binding(
+    keys: &Input<KeyCode>,
+    gamepad_buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+    gamepads: &Gamepads,
+) -> Option<Binding> {
+    if let Some(key) = keys.get_just_pressed().next() {
+        return Some(Binding::Key(*key));
+    }
+    if let Some(button) = gamepad_buttons.get_just_pressed().next() {
+        return Some(Binding::Button(button.button_type));
+    }
+    let sticks = [
+        GamepadAxisType::LeftStickX,
+        GamepadAxisType::LeftStickY,
+        GamepadAxisType::RightStickX,
+        GamepadAxisType::RightStickY,
+    ];
+    gamepads.iter().find_map(|gamepad| {
+        sticks.into_iter().find_map(|axis| {
+            let value = axes.get(GamepadAxis::new(gamepad, axis))?;
+            (value.abs() >= PRESS_THRESHOLD).then_some(Binding::Stick {
+                axis,
+                positive: value > 0.0,
+            })
+        })
+    })
+}
@@ -0,0 +1,379 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car::{Car, Drivable, VehicleConfig, TIRE_RADIUS},
+    controls::VehicleInput,
+    game_state::GameState,
+};
+
+/// Speed, in meters per second, below which a drivable counts as stopped and can be put into or
+/// taken out of reverse.
+const REVERSE_ENGAGE_SPEED: f32 = 0.5;
+
+pub struct DrivetrainPlugin;
+
+impl Plugin for DrivetrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Transmission>().add_systems(
+            Update,
+            (
+                shift_gears.run_if(in_state(GameState::Playing)),
+                update_engine,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// The engine and gearbox that turn the throttle into torque at the driven wheels.
+#[derive(Reflect, Clone, Serialize, Deserialize)]
+pub struct DrivetrainConfig {
+    /// Slowest the engine turns, in revolutions per minute.
+    pub idle_rpm: f32,
+    /// Fastest the engine turns. It makes no torque past this.
+    pub redline_rpm: f32,
+    /// Torque, in newton meters, at the peak of the engine's torque curve.
+    pub max_torque: f32,
+    #[serde(default)]
+    pub power_curve: PowerCurve,
+    /// Ratio of engine to gearbox output speed in each forward gear, starting with first.
+    pub gear_ratios: Vec<f32>,
+    pub reverse_ratio: f32,
+    /// Ratio of gearbox output to wheel speed, applied in every gear.
+    pub final_drive: f32,
+    /// Whether the gearbox changes between forward gears by itself. With a manual gearbox the
+    /// player changes every gear.
+    pub automatic: bool,
+    /// Engine speed at which an automatic gearbox changes up a gear.
+    pub shift_up_rpm: f32,
+    /// Engine speed at which an automatic gearbox changes down a gear.
+    pub shift_down_rpm: f32,
+}
+
+impl Default for DrivetrainConfig {
+    fn default() -> Self {
+        Self {
+            idle_rpm: 900.0,
+            redline_rpm: 6000.0,
+            max_torque: 8.0,
+            power_curve: PowerCurve::default(),
+            gear_ratios: vec![3.0, 2.0, 1.4, 1.0],
+            reverse_ratio: 3.2,
+            final_drive: 4.0,
+            automatic: true,
+            shift_up_rpm: 5500.0,
+            shift_down_rpm: 2500.0,
+        }
+    }
+}
+
+impl DrivetrainConfig {
+    /// Ratio of engine to wheel speed in `gear`, or `None` in neutral.
+    fn overall_ratio(&self, gear: Gear) -> Option<f32> {
+        match gear {
+            Gear::Reverse => Some(self.reverse_ratio * self.final_drive),
+            Gear::Neutral => None,
+            Gear::Forward(index) => Some(self.gear_ratios.get(index)? * self.final_drive),
+        }
+    }
+
+    /// Engine speed in `gear` with the wheels rolling at `wheel_speed` meters per second.
+    fn rpm(&self, gear: Gear, wheel_speed: f32) -> Option<f32> {
+        let wheel_rpm = wheel_speed.abs() / TIRE_RADIUS * 60.0 / (2.0 * PI);
+        Some((wheel_rpm * self.overall_ratio(gear)?).max(self.idle_rpm))
+    }
+}
+
+#[derive(Reflect, Clone, Copy, PartialEq)]
+pub enum Gear {
+    Reverse,
+    Neutral,
+    /// A forward gear, counting from 0 for first.
+    Forward(usize),
+}
+
+/// Which gear a drivable is in and how fast its engine is turning.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Transmission {
+    pub gear: Gear,
+    pub rpm: f32,
+}
+
+impl Default for Transmission {
+    fn default() -> Self {
+        Self {
+            gear: Gear::Forward(0),
+            rpm: 0.0,
+        }
+    }
+}
+
+impl Transmission {
+    /// What the gear selector shows, e.g. `"R"`, `"N"`, `"D"` or `"3"`.
+    pub fn gear_label(&self, config: &DrivetrainConfig) -> String {
+        match self.gear {
+            Gear::Reverse => "R".to_string(),
+            Gear::Neutral => "N".to_string(),
+            Gear::Forward(_) if config.automatic => "D".to_string(),
+            Gear::Forward(index) => (index + 1).to_string(),
+        }
+    }
+
+    /// Total torque at the driven wheels for the given throttle, negative when it pushes the
+    /// vehicle backwards.
+    pub fn wheel_torque(&self, config: &DrivetrainConfig, throttle: f32) -> f32 {
+        let Some(ratio) = config.overall_ratio(self.gear) else {
+            return 0.0;
+        };
+        let direction = if self.gear == Gear::Reverse {
+            -1.0
+        } else {
+            1.0
+        };
+        let engine_torque =
+            config.max_torque * config.power_curve.sample(self.rpm / config.redline_rpm);
+        direction * throttle * engine_torque * ratio
+    }
+}
+
+/// The shape of an engine's torque curve, as points joined by straight lines.
+///
+/// Each point is an engine speed as a fraction of the redline, and the torque there as a
+/// fraction of `DrivetrainConfig::max_torque`.
+#[derive(Reflect, Clone, Serialize, Deserialize)]
+pub struct PowerCurve {
+    pub points: Vec<Vec2>,
+}
+
+impl Default for PowerCurve {
+    fn default() -> Self {
+        Self {
+            points: vec![
+                Vec2::new(0.0, 0.52),
+                Vec2::new(0.2, 0.7),
+                Vec2::new(0.3, 0.82),
+                Vec2::new(0.4, 1.0),
+                Vec2::new(0.7, 1.0),
+                Vec2::new(0.8, 0.9),
+                Vec2::new(0.9, 0.78),
+                Vec2::new(1.0, 0.6),
+            ],
+        }
+    }
+}
+
+impl PowerCurve {
+    /// Fraction of the peak torque at `rpm_ratio` of the redline. Below the first point the
+    /// curve stays level, and past the redline the engine makes nothing.
+    pub fn sample(&self, rpm_ratio: f32) -> f32 {
+        if rpm_ratio > 1.0 {
+            return 0.0;
+        }
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return 0.0;
+        };
+        if rpm_ratio <= first.x {
+            return first.y;
+        }
+        self.points
+            .windows(2)
+            .find(|pair| rpm_ratio <= pair[1].x)
+            .map_or(last.y, |pair| {
+                let t = (rpm_ratio - pair[0].x) / (pair[1].x - pair[0].x);
+                pair[0].y + (pair[1].y - pair[0].y) * t
+            })
+    }
+
+    /// Checks that there are at least two points, in order of engine speed, all within the
+    /// redline and none with negative torque.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.len() < 2 {
+            return Err("a power curve needs at least two points".to_string());
+        }
+        for point in &self.points {
+            if !((0.0..=1.0).contains(&point.x) && point.y.is_finite() && point.y >= 0.0) {
+                return Err(format!(
+                    "power curve points must be between 0 and 1 rpm and not negative, got {}",
+                    point
+                ));
+            }
+        }
+        if self.points.windows(2).any(|pair| pair[0].x >= pair[1].x) {
+            return Err("power curve points must go up in rpm".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn forward_speed(transform: &Transform, velocity: &Velocity) -> f32 {
+    velocity.linvel.dot(transform.rotation * Vec3::X)
+}
+
+/// Moves the car's gear selector. Reverse only goes in once the car has stopped, and so does
+/// first gear coming out of reverse.
+fn shift_gears(
+    vehicle_input: Res<VehicleInput>,
+    mut car: Query<(&Transform, &Velocity, &VehicleConfig, &mut Transmission), With<Car>>,
+) {
+    if !(vehicle_input.shift_up || vehicle_input.shift_down) {
+        return;
+    }
+    let Ok((transform, velocity, config, mut transmission)) = car.get_single_mut() else {
+        return;
+    };
+    let drivetrain = &config.drivetrain;
+    let speed = forward_speed(transform, velocity);
+    let top_gear = drivetrain.gear_ratios.len().saturating_sub(1);
+    let gear = match transmission.gear {
+        gear if vehicle_input.shift_up && vehicle_input.shift_down => gear,
+        Gear::Reverse if vehicle_input.shift_up => Gear::Neutral,
+        Gear::Neutral if vehicle_input.shift_up && speed > -REVERSE_ENGAGE_SPEED => {
+            Gear::Forward(0)
+        }
+        Gear::Neutral if vehicle_input.shift_down && speed < REVERSE_ENGAGE_SPEED => Gear::Reverse,
+        Gear::Forward(index) if vehicle_input.shift_up && !drivetrain.automatic => {
+            Gear::Forward((index + 1).min(top_gear))
+        }
+        Gear::Forward(index) if vehicle_input.shift_down && !drivetrain.automatic && index > 0 => {
+            Gear::Forward(index - 1)
+        }
+        Gear::Forward(_) if vehicle_input.shift_down => Gear::Neutral,
+        gear => gear,
+    };
+    transmission.gear = gear;
+}
+
+/// Works out each engine's speed from how fast its wheels are rolling, and changes gear for
+/// automatic gearboxes.
+pub fn update_engine(
+    vehicle_input: Res<VehicleInput>,
+    mut drivables: Query<
+        (
+            &Transform,
+            &Velocity,
+            &VehicleConfig,
+            &mut Transmission,
+            Option<&Car>,
+        ),
+        With<Drivable>,
+    >,
+) {
+    for (transform, velocity, config, mut transmission, car) in &mut drivables {
+        let drivetrain = &config.drivetrain;
+        let speed = forward_speed(transform, velocity);
+        if let Gear::Forward(index) = transmission.gear {
+            if drivetrain.automatic {
+                let rpm = drivetrain.rpm(transmission.gear, speed).unwrap_or(0.0);
+                if rpm > drivetrain.shift_up_rpm && index + 1 < drivetrain.gear_ratios.len() {
+                    transmission.gear = Gear::Forward(index + 1);
+                } else if rpm < drivetrain.shift_down_rpm && index > 0 {
+                    transmission.gear = Gear::Forward(index - 1);
+                }
+            }
+        }
+        transmission.rpm = match drivetrain.rpm(transmission.gear, speed) {
+            Some(rpm) => rpm,
+            // in neutral the engine revs freely with the throttle
+            None => {
+                let throttle = if car.is_some() {
+                    vehicle_input.throttle
+                } else {
+                    0.0
+                };
+                drivetrain.idle_rpm + throttle * (drivetrain.redline_rpm - drivetrain.idle_rpm)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The hard-coded curve the power curve replaced, as a fraction of the peak.
+    fn old_lookup_power(speed_ratio: f32) -> f32 {
+        if speed_ratio < 0.4 {
+            -(-0.5 * speed_ratio + 0.3).log(10.0)
+        } else if speed_ratio <= 0.698 {
+            1.0
+        } else if speed_ratio <= 1.0 {
+            (-5.0 * speed_ratio + 6.0).log(10.0) + 0.6
+        } else {
+            0.0
+        }
+    }
+
+    /// Straight lines can't follow the old logarithmic curve exactly. Between points they stray
+    /// by up to 0.8% of the peak torque, around 12% of the redline.
+    const OLD_CURVE_TOLERANCE: f32 = 0.01;
+
+    #[test]
+    fn default_curve_matches_old_lookup_power() {
+        let curve = PowerCurve::default();
+        // every thousandth of the rev range, so points between the breakpoints are checked too
+        for i in 0..=1000 {
+            let rpm_ratio = i as f32 / 1000.0;
+            let old = old_lookup_power(rpm_ratio);
+            let sampled = curve.sample(rpm_ratio);
+            assert!(
+                (sampled - old).abs() < OLD_CURVE_TOLERANCE,
+                "at {} the curve gives {} but lookup_power gave {}",
+                rpm_ratio,
+                sampled,
+                old
+            );
+        }
+    }
+
+    #[test]
+    fn default_curve_matches_old_lookup_power_values() {
+        let curve = PowerCurve::default();
+        // what lookup_power gave part way between the breakpoints
+        for (rpm_ratio, old) in [(0.1, 0.6021), (0.35, 0.9031), (0.55, 1.0), (0.95, 0.6969)] {
+            assert!(
+                (curve.sample(rpm_ratio) - old).abs() < OLD_CURVE_TOLERANCE,
+                "at {} the curve gives {} instead of {}",
+                rpm_ratio,
+                curve.sample(rpm_ratio),
+                old
+            );
+        }
+    }
+
+    #[test]
+    fn sample_interpolates_between_points() {
+        let curve = PowerCurve {
+            points: vec![Vec2::new(0.2, 0.4), Vec2::new(0.6, 0.8)],
+        };
+        assert!((curve.sample(0.4) - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sample_stays_level_below_first_point() {
+        let curve = PowerCurve {
+            points: vec![Vec2::new(0.2, 0.4), Vec2::new(0.6, 0.8)],
+        };
+        assert_eq!(curve.sample(0.0), 0.4);
+        assert_eq!(curve.sample(-1.0), 0.4);
+    }
+
+    #[test]
+    fn sample_holds_last_point_up_to_redline_and_is_zero_past_it() {
+        let curve = PowerCurve {
+            points: vec![Vec2::new(0.2, 0.4), Vec2::new(0.6, 0.8)],
+        };
+        assert_eq!(curve.sample(1.0), 0.8);
+        assert_eq!(curve.sample(1.01), 0.0);
+    }
+
+    #[test]
+    fn sample_of_empty_curve_is_zero() {
+        let curve = PowerCurve { points: vec![] };
+        assert_eq!(curve.sample(0.5), 0.0);
+    }
+}
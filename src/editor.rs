@@ -0,0 +1,748 @@
+use std::f32::consts::PI;
+
+use bevy::{input::mouse::MouseWheel, prelude::*, utils::HashMap, window::PrimaryWindow};
+use bevy_inspector_egui::{
+    bevy_egui::EguiContexts,
+    egui::{self, ComboBox, DragValue},
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    car::SpawnPoint,
+    game_state::GameState,
+    level::{
+        self, despawn_level, BoxDefinition, CheckpointDefinition, CurrentLevel, LevelDefinition,
+        LevelEntity, Levels, ParkingSpotDefinition, PropDefinition,
+    },
+    parking_spot::{ParkingSpot, RequiredVehicle},
+};
+
+/// Meters per second the editor camera pans at.
+const CAMERA_SPEED: f32 = 20.0;
+/// Radians per second the editor camera turns at.
+const CAMERA_TURN_SPEED: f32 = 1.5;
+/// How far, in meters, the editor looks for something under the cursor.
+const PICK_DISTANCE: f32 = 500.0;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Editor>()
+            .add_systems(OnEnter(GameState::Editing), reload_level)
+            .add_systems(OnExit(GameState::Editing), reload_level)
+            .add_systems(
+                Update,
+                (
+                    editor_ui_system,
+                    editor_camera,
+                    pick_and_drag,
+                    draw_editor_gizmos,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Editing)),
+            );
+    }
+}
+
+/// Something in the level the editor can move, and what it needs to write it back out.
+#[derive(Component, Clone)]
+pub enum Editable {
+    ParkingSpot {
+        /// The spot's name in the level's file, so a renamed spot keeps its place among the
+        /// objectives. `None` for spots added in the editor.
+        loaded_as: Option<String>,
+    },
+    StaticCollider(BoxDefinition),
+    /// The prop as it was spawned. Its transform is read back from the entity when exported.
+    Prop(PropDefinition),
+    /// Where the rig starts.
+    SpawnPoint,
+    Checkpoint,
+}
+
+impl Editable {
+    fn can_scale(&self) -> bool {
+        match self {
+            Editable::ParkingSpot { .. } | Editable::StaticCollider(_) | Editable::Checkpoint => {
+                true
+            }
+            Editable::Prop(prop) => prop.stack.is_none(),
+            Editable::SpawnPoint => false,
+        }
+    }
+
+    /// Center and half size of the thing in its own space, before its transform's scale.
+    fn local_bounds(&self) -> (Vec3, Vec3) {
+        match self {
+            Editable::ParkingSpot { .. } | Editable::Checkpoint => (Vec3::ZERO, Vec3::splat(0.5)),
+            Editable::StaticCollider(shape) => (Vec3::ZERO, shape.half_extents),
+            Editable::Prop(prop) => {
+                let half_extents = prop.shape.half_extents;
+                match &prop.stack {
+                    None => (Vec3::ZERO, half_extents),
+                    Some(stack) => {
+                        let far_corner = Vec3::new(
+                            0.0,
+                            stack.rows.saturating_sub(1) as f32 * stack.spacing,
+                            stack.columns.saturating_sub(1) as f32 * stack.spacing,
+                        );
+                        (far_corner / 2.0, half_extents + far_corner / 2.0)
+                    }
+                }
+            }
+            Editable::SpawnPoint => (Vec3::ZERO, Vec3::new(1.0, 0.5, 0.5)),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum EditTool {
+    #[default]
+    Move,
+    Rotate,
+    Scale,
+}
+
+/// Where the cursor was on the ground, and where the selection was, when a drag started.
+struct Drag {
+    start_point: Vec3,
+    start_transform: Transform,
+}
+
+#[derive(Resource, Default)]
+struct Editor {
+    selected: Option<Entity>,
+    tool: EditTool,
+    drag: Option<Drag>,
+    /// What's typed in the name box for the selection, kept separately so a name that's empty or
+    /// already taken can be shown without being applied.
+    new_name: Option<(Entity, String)>,
+    /// Result of the last export.
+    status: String,
+}
+
+/// Puts the level back the way its file describes, so the editor never starts from a layout the
+/// player has knocked about, and unsaved edits don't leak into play.
+fn reload_level(
+    mut commands: Commands,
+    mut current_level: ResMut<CurrentLevel>,
+    mut editor: ResMut<Editor>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+) {
+    despawn_level(&mut commands, &level_entities);
+    current_level.spawned = false;
+    *editor = Editor::default();
+}
+
+/// Yaw of a transform, in the same convention as level headings.
+fn heading(transform: &Transform) -> f32 {
+    transform.rotation.to_euler(EulerRot::YXZ).0
+}
+
+/// Angle of a direction on the ground, in the same convention as level headings.
+fn ground_angle(direction: Vec3) -> f32 {
+    (-direction.z).atan2(direction.x)
+}
+
+fn cursor_ray(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Ray> {
+    let cursor_position = windows.get_single().ok()?.cursor_position()?;
+    let (camera, camera_transform) = cameras.get_single().ok()?;
+    camera.viewport_to_world(camera_transform, cursor_position)
+}
+
+/// Where a ray meets the horizontal plane at the given height.
+fn ground_point(ray: Ray, height: f32) -> Option<Vec3> {
+    ray.intersect_plane(Vec3::Y * height, Vec3::Y)
+        .map(|distance| ray.get_point(distance))
+}
+
+fn editor_camera(
+    mut contexts: EguiContexts,
+    keys: Res<Input<KeyCode>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+    time: Res<Time>,
+) {
+    let Ok(mut camera_transform) = cameras.get_single_mut() else {
+        return;
+    };
+    let zoom: f32 = mouse_wheel_events.iter().map(|event| event.y).sum();
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    let forward = Vec3::new(
+        camera_transform.forward().x,
+        0.0,
+        camera_transform.forward().z,
+    )
+    .normalize_or_zero();
+    let right =
+        Vec3::new(camera_transform.right().x, 0.0, camera_transform.right().z).normalize_or_zero();
+    let mut pan = Vec3::ZERO;
+    for (key, direction) in [
+        (KeyCode::W, forward),
+        (KeyCode::S, -forward),
+        (KeyCode::D, right),
+        (KeyCode::A, -right),
+    ] {
+        if keys.pressed(key) {
+            pan += direction;
+        }
+    }
+    let mut turn = 0.0;
+    if keys.pressed(KeyCode::Q) {
+        turn += 1.0;
+    }
+    if keys.pressed(KeyCode::E) {
+        turn -= 1.0;
+    }
+
+    let camera_forward = camera_transform.forward();
+    camera_transform.translation +=
+        pan * CAMERA_SPEED * time.delta_seconds() + camera_forward * zoom;
+    camera_transform.rotate_y(turn * CAMERA_TURN_SPEED * time.delta_seconds());
+}
+
+/// Selects whatever editable thing is clicked on, and moves, turns or resizes it while the mouse
+/// is held, depending on the current tool.
+#[allow(clippy::too_many_arguments)]
+fn pick_and_drag(
+    mut contexts: EguiContexts,
+    mouse_buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    rapier_context: Res<RapierContext>,
+    parents: Query<&Parent>,
+    mut editables: Query<(&Editable, &mut Transform)>,
+    mut editor: ResMut<Editor>,
+) {
+    if mouse_buttons.just_released(MouseButton::Left) {
+        editor.drag = None;
+    }
+    let Some(ray) = cursor_ray(&windows, &cameras) else {
+        return;
+    };
+
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        if contexts.ctx_mut().is_pointer_over_area() {
+            return;
+        }
+        // props are picked by their children, which hold the colliders
+        let editable_of = |entity: Entity| {
+            if editables.contains(entity) {
+                Some(entity)
+            } else {
+                parents
+                    .get(entity)
+                    .ok()
+                    .map(Parent::get)
+                    .filter(|parent| editables.contains(*parent))
+            }
+        };
+        let picked = rapier_context
+            .cast_ray(
+                ray.origin,
+                ray.direction,
+                PICK_DISTANCE,
+                true,
+                QueryFilter::default().predicate(&|entity| editable_of(entity).is_some()),
+            )
+            .and_then(|(entity, _)| editable_of(entity));
+        editor.selected = picked;
+        editor.drag = picked.and_then(|entity| {
+            let (_, transform) = editables.get(entity).ok()?;
+            Some(Drag {
+                start_point: ground_point(ray, transform.translation.y)?,
+                start_transform: *transform,
+            })
+        });
+        return;
+    }
+
+    let (Some(selected), Some(drag)) = (editor.selected, &editor.drag) else {
+        return;
+    };
+    let Ok((editable, mut transform)) = editables.get_mut(selected) else {
+        return;
+    };
+    let start = drag.start_transform;
+    let Some(point) = ground_point(ray, start.translation.y) else {
+        return;
+    };
+    match editor.tool {
+        EditTool::Move => {
+            transform.translation = start.translation + (point - drag.start_point);
+        }
+        EditTool::Rotate => {
+            let turned = ground_angle(point - start.translation)
+                - ground_angle(drag.start_point - start.translation);
+            transform.rotation = Quat::from_rotation_y(turned) * start.rotation;
+        }
+        EditTool::Scale => {
+            if !editable.can_scale() {
+                return;
+            }
+            let to_local = start.rotation.inverse();
+            let start_offset = to_local * (drag.start_point - start.translation);
+            let offset = to_local * (point - start.translation);
+            let ratio = |from: f32, to: f32| {
+                if from.abs() < 0.1 {
+                    1.0
+                } else {
+                    (to.abs() / from.abs()).max(0.05)
+                }
+            };
+            transform.scale = start.scale
+                * Vec3::new(
+                    ratio(start_offset.x, offset.x),
+                    1.0,
+                    ratio(start_offset.z, offset.z),
+                );
+        }
+    }
+}
+
+fn draw_editor_gizmos(
+    mut gizmos: Gizmos,
+    editables: Query<(Entity, &Editable, &Transform)>,
+    editor: Res<Editor>,
+) {
+    for (entity, editable, transform) in &editables {
+        let selected = editor.selected == Some(entity);
+        if let Editable::SpawnPoint = editable {
+            let forward = transform.rotation * Vec3::X;
+            gizmos.line(
+                transform.translation,
+                transform.translation + forward * 3.0,
+                Color::CYAN,
+            );
+            gizmos.circle(transform.translation, Vec3::Y, 1.0, Color::CYAN);
+        }
+        if !selected {
+            continue;
+        }
+
+        let (center, half_size) = editable.local_bounds();
+        let half_size = half_size * transform.scale;
+        let world_center = transform.transform_point(center);
+        gizmos.cuboid(
+            Transform::from_translation(world_center)
+                .with_rotation(transform.rotation)
+                .with_scale(half_size * 2.0 + 0.1),
+            Color::YELLOW,
+        );
+        let x_axis = transform.rotation * Vec3::X;
+        let z_axis = transform.rotation * Vec3::Z;
+        match editor.tool {
+            EditTool::Move => {
+                gizmos.line(
+                    world_center,
+                    world_center + x_axis * (half_size.x + 1.0),
+                    Color::RED,
+                );
+                gizmos.line(
+                    world_center,
+                    world_center + z_axis * (half_size.z + 1.0),
+                    Color::BLUE,
+                );
+            }
+            EditTool::Rotate => {
+                gizmos.circle(
+                    world_center,
+                    Vec3::Y,
+                    half_size.x.max(half_size.z) + 0.5,
+                    Color::YELLOW,
+                );
+            }
+            EditTool::Scale if editable.can_scale() => {
+                for corner in [
+                    x_axis * half_size.x + z_axis * half_size.z,
+                    x_axis * half_size.x - z_axis * half_size.z,
+                ] {
+                    gizmos.line(world_center - corner, world_center + corner, Color::YELLOW);
+                }
+            }
+            EditTool::Scale => {}
+        }
+    }
+}
+
+/// Somewhere on the ground in the middle of the screen, to put new things.
+fn placement_point(
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+    rapier_context: &RapierContext,
+) -> Vec3 {
+    let Ok((_, camera_transform)) = cameras.get_single() else {
+        return Vec3::ZERO;
+    };
+    let origin = camera_transform.translation();
+    let direction = camera_transform.forward();
+    match rapier_context.cast_ray(
+        origin,
+        direction,
+        PICK_DISTANCE,
+        true,
+        QueryFilter::default().exclude_sensors(),
+    ) {
+        Some((_, distance)) => origin + direction * distance,
+        None => origin + direction * 20.0,
+    }
+}
+
+/// Gathers everything in the level as it is now into a level definition, keeping whatever the
+/// editor can't change from the level's file.
+fn build_level_definition(
+    original: &LevelDefinition,
+    editables: &Query<(
+        Entity,
+        &Editable,
+        &mut Transform,
+        &mut Name,
+        Option<&mut ParkingSpot>,
+        Option<&mut SpawnPoint>,
+    )>,
+) -> LevelDefinition {
+    let mut level = original.clone();
+    level.static_colliders.clear();
+    level.props.clear();
+    level.parking_spots.clear();
+    level.checkpoints.clear();
+    let mut renamed_spots = HashMap::new();
+    let mut added_spots = Vec::new();
+    for (_, editable, transform, name, parking_spot, spawn_point) in editables {
+        let translation = transform.translation;
+        let heading = heading(transform);
+        let moved_box = |shape: &BoxDefinition| BoxDefinition {
+            name: name.to_string(),
+            translation,
+            heading,
+            half_extents: shape.half_extents * transform.scale,
+            texture: shape.texture.clone(),
+        };
+        match editable {
+            Editable::ParkingSpot { loaded_as } => {
+                let Some(parking_spot) = parking_spot else {
+                    continue;
+                };
+                match loaded_as {
+                    Some(loaded_as) => {
+                        renamed_spots.insert(loaded_as.clone(), name.to_string());
+                    }
+                    None => added_spots.push(name.to_string()),
+                }
+                level.parking_spots.push(ParkingSpotDefinition {
+                    name: name.to_string(),
+                    translation,
+                    heading,
+                    size: transform.scale,
+                    required_vehicle: parking_spot.required_vehicle,
+                    heading_tolerance: parking_spot.heading_tolerance,
+                });
+            }
+            Editable::StaticCollider(shape) => level.static_colliders.push(moved_box(shape)),
+            Editable::Checkpoint => level.checkpoints.push(CheckpointDefinition {
+                name: name.to_string(),
+                translation,
+                heading,
+                size: transform.scale,
+            }),
+            Editable::Prop(prop) => level.props.push(PropDefinition {
+                shape: moved_box(&prop.shape),
+                stack: prop.stack.clone(),
+            }),
+            Editable::SpawnPoint => {
+                level.rig.translation = translation;
+                level.rig.heading = heading;
+                if let Some(spawn_point) = spawn_point {
+                    level.rig.hitch_angles = spawn_point.hitch_angles.clone();
+                }
+            }
+        }
+    }
+    level.static_colliders.sort_by(|a, b| a.name.cmp(&b.name));
+    level.props.sort_by(|a, b| a.shape.name.cmp(&b.shape.name));
+    level.parking_spots.sort_by(|a, b| a.name.cmp(&b.name));
+    level.checkpoints.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // objectives keep their order through renames, spots that weren't objectives stay that way,
+    // and spots added in the editor become objectives at the end
+    added_spots.sort();
+    level.objectives = original
+        .objectives
+        .iter()
+        .filter_map(|objective| renamed_spots.get(objective).cloned())
+        .chain(added_spots)
+        .collect();
+    level
+}
+
+fn export_level(file_name: &str, level: &LevelDefinition) -> Result<(), String> {
+    level.validate()?;
+    let contents = ron::ser::to_string_pretty(level, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())?;
+    std::fs::write(format!("assets/{}", level::level_path(file_name)), contents)
+        .map_err(|e| e.to_string())
+}
+
+/// A name that isn't used by anything else in the level yet.
+fn unused_name(base: &str, names: &[String]) -> String {
+    (1..)
+        .map(|i| format!("{} {}", base, i))
+        .find(|name| !names.contains(name))
+        .unwrap()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn editor_ui_system(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut editor: ResMut<Editor>,
+    mut editables: Query<(
+        Entity,
+        &Editable,
+        &mut Transform,
+        &mut Name,
+        Option<&mut ParkingSpot>,
+        Option<&mut SpawnPoint>,
+    )>,
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+    level_assets: Res<Assets<LevelDefinition>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    rapier_context: Res<RapierContext>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(level_info) = levels.0.get(current_level.index) else {
+        return;
+    };
+    egui::Window::new("Level Editor")
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(10.0, 10.0))
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Editing {}",
+                levels.display_name(current_level.index, &level_assets)
+            ));
+            ui.label("WASD to pan, Q/E to turn, scroll to zoom. Click to select, drag to edit.");
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut editor.tool, EditTool::Move, "Move");
+                ui.selectable_value(&mut editor.tool, EditTool::Rotate, "Rotate");
+                ui.selectable_value(&mut editor.tool, EditTool::Scale, "Scale");
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Add:");
+                let add_spot = ui.button("Parking spot").clicked();
+                let add_wall = ui.button("Wall").clicked();
+                let add_crate = ui.button("Crate").clicked();
+                let add_checkpoint = ui.button("Checkpoint").clicked();
+                if !(add_spot || add_wall || add_crate || add_checkpoint) {
+                    return;
+                }
+                let names: Vec<String> = editables
+                    .iter()
+                    .map(|(_, _, _, name, ..)| name.to_string())
+                    .collect();
+                let translation = placement_point(&cameras, &rapier_context);
+                let added = if add_spot {
+                    let spot = level::spawn_level_parking_spot(
+                        &mut commands,
+                        &ParkingSpotDefinition {
+                            name: unused_name("Spot", &names),
+                            translation,
+                            heading: 0.0,
+                            size: Vec3::new(10.0, 1.5, 4.0),
+                            required_vehicle: RequiredVehicle::Trailer,
+                            heading_tolerance: 0.2,
+                        },
+                    );
+                    commands
+                        .entity(spot)
+                        .insert(Editable::ParkingSpot { loaded_as: None });
+                    spot
+                } else if add_wall {
+                    level::spawn_static_collider(
+                        &mut commands,
+                        &BoxDefinition {
+                            name: unused_name("Wall", &names),
+                            translation: translation + Vec3::Y * 2.0,
+                            heading: 0.0,
+                            half_extents: Vec3::new(0.5, 2.0, 5.0),
+                            texture: Some("floor.png".to_string()),
+                        },
+                        &mut meshes,
+                        &mut materials,
+                        &asset_server,
+                    )
+                } else if add_checkpoint {
+                    level::spawn_level_checkpoint(
+                        &mut commands,
+                        &CheckpointDefinition {
+                            name: unused_name("Checkpoint", &names),
+                            translation: translation + Vec3::Y * 1.5,
+                            heading: 0.0,
+                            size: Vec3::new(2.0, 3.0, 8.0),
+                        },
+                    )
+                } else {
+                    level::spawn_prop(
+                        &mut commands,
+                        &PropDefinition {
+                            shape: BoxDefinition {
+                                name: unused_name("Crate", &names),
+                                translation: translation + Vec3::Y * 0.5,
+                                heading: 0.0,
+                                half_extents: Vec3::splat(0.5),
+                                texture: Some("floor.png".to_string()),
+                            },
+                            stack: None,
+                        },
+                        &mut meshes,
+                        &mut materials,
+                        &asset_server,
+                    )
+                };
+                editor.selected = Some(added);
+            });
+
+            ui.separator();
+            let other_names: Vec<String> = editables
+                .iter()
+                .filter(|(entity, ..)| Some(*entity) != editor.selected)
+                .map(|(_, _, _, name, ..)| name.to_string())
+                .collect();
+            let selected = editor
+                .selected
+                .and_then(|selected| editables.get_mut(selected).ok());
+            match selected {
+                None => {
+                    ui.label("Nothing selected");
+                }
+                Some((entity, editable, mut transform, mut name, parking_spot, spawn_point)) => {
+                    let mut new_name = match editor.new_name.take() {
+                        Some((renaming, new_name)) if renaming == entity => new_name,
+                        _ => name.to_string(),
+                    };
+                    let renameable = !matches!(editable, Editable::SpawnPoint);
+                    if ui
+                        .add_enabled(renameable, egui::TextEdit::singleline(&mut new_name))
+                        .changed()
+                    {
+                        if new_name.trim().is_empty() {
+                            editor.status = "Names can't be empty".to_string();
+                        } else if other_names.contains(&new_name) {
+                            editor.status = format!("{} is already taken", new_name);
+                        } else {
+                            name.set(new_name.clone());
+                            editor.status.clear();
+                        }
+                    }
+                    editor.new_name = Some((entity, new_name));
+                    ui.horizontal(|ui| {
+                        ui.label("Position");
+                        ui.add(DragValue::new(&mut transform.translation.x).speed(0.1));
+                        ui.add(DragValue::new(&mut transform.translation.y).speed(0.1));
+                        ui.add(DragValue::new(&mut transform.translation.z).speed(0.1));
+                    });
+                    let mut heading_degrees = heading(&transform).to_degrees();
+                    ui.horizontal(|ui| {
+                        ui.label("Heading");
+                        if ui
+                            .add(DragValue::new(&mut heading_degrees).suffix("°"))
+                            .changed()
+                        {
+                            transform.rotation =
+                                Quat::from_rotation_y(heading_degrees.to_radians());
+                        }
+                    });
+                    if editable.can_scale() {
+                        ui.horizontal(|ui| {
+                            ui.label("Scale");
+                            let scale = &mut transform.scale;
+                            for axis in [&mut scale.x, &mut scale.y, &mut scale.z] {
+                                ui.add(DragValue::new(axis).speed(0.05).clamp_range(0.05..=100.0));
+                            }
+                        });
+                    }
+                    if let Some(mut spawn_point) = spawn_point {
+                        let trailers = spawn_point.vehicles.len().saturating_sub(1);
+                        spawn_point.hitch_angles.resize(trailers, 0.0);
+                        for (i, hitch_angle) in spawn_point.hitch_angles.iter_mut().enumerate() {
+                            let mut degrees = hitch_angle.to_degrees();
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Hitch {} angle", i + 1));
+                                if ui
+                                    .add(
+                                        DragValue::new(&mut degrees)
+                                            .suffix("°")
+                                            .clamp_range(-90.0..=90.0),
+                                    )
+                                    .changed()
+                                {
+                                    *hitch_angle = degrees.to_radians();
+                                }
+                            });
+                        }
+                    }
+                    if let Some(mut parking_spot) = parking_spot {
+                        ComboBox::from_label("Required vehicle")
+                            .selected_text(format!("{:?}", parking_spot.required_vehicle))
+                            .show_ui(ui, |ui| {
+                                for required_vehicle in [
+                                    RequiredVehicle::Car,
+                                    RequiredVehicle::Trailer,
+                                    RequiredVehicle::Both,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut parking_spot.required_vehicle,
+                                        required_vehicle,
+                                        format!("{:?}", required_vehicle),
+                                    );
+                                }
+                            });
+                        ui.add(
+                            egui::Slider::new(&mut parking_spot.heading_tolerance, 0.0..=PI / 2.0)
+                                .text("heading tolerance"),
+                        );
+                    }
+                    if renameable && ui.button("Delete").clicked() {
+                        commands.entity(entity).despawn_recursive();
+                        editor.selected = None;
+                    }
+                }
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Export").clicked() {
+                    editor.status = match level_assets.get(&level_info.handle) {
+                        None => "The level hasn't loaded yet".to_string(),
+                        Some(original) => {
+                            let level = build_level_definition(original, &editables);
+                            match export_level(&level_info.file_name, &level) {
+                                Ok(()) => {
+                                    format!("Saved {}", level::level_path(&level_info.file_name))
+                                }
+                                Err(e) => format!("Couldn't save: {}", e),
+                            }
+                        }
+                    };
+                }
+                if ui.button("Main menu").clicked() {
+                    next_state.set(GameState::MainMenu);
+                }
+            });
+            if !editor.status.is_empty() {
+                ui.label(&editor.status);
+            }
+        });
+}
@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    car::{Car, ResetRig, Trailer},
+    controls::Action,
+    parking_spot::{Objectives, ParkingSpot, STOPPED_SPEED},
+    score::ParkingScores,
+};
+
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .init_resource::<LevelTimer>()
+            .init_resource::<Attempts>()
+            .init_resource::<FailReason>()
+            .add_systems(OnEnter(GameState::MainMenu), pause_physics)
+            .add_systems(OnEnter(GameState::Playing), resume_physics)
+            .add_systems(OnExit(GameState::Playing), pause_physics)
+            .add_systems(
+                Update,
+                (
+                    start_attempt,
+                    (tick_level_timer, check_time_limit, check_level_complete)
+                        .chain()
+                        .run_if(in_state(GameState::Playing)),
+                    toggle_pause
+                        .run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))),
+                ),
+            );
+    }
+}
+
+#[derive(States, Default, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    LevelSelect,
+    /// Changing what the keys and gamepad buttons do.
+    Controls,
+    Playing,
+    Paused,
+    /// Every objective is done and the rig has come to a stop.
+    LevelComplete,
+    /// The attempt is over, see `FailReason` for why.
+    Failed,
+    /// Laying out the current level.
+    Editing,
+}
+
+/// How long the current attempt has been going.
+#[derive(Resource, Default)]
+pub struct LevelTimer {
+    /// Seconds spent in `GameState::Playing` this attempt.
+    pub elapsed: f32,
+    /// Seconds the player has before the attempt fails, if there's a limit.
+    pub time_limit: Option<f32>,
+}
+
+impl LevelTimer {
+    pub fn remaining(&self) -> Option<f32> {
+        self.time_limit
+            .map(|time_limit| (time_limit - self.elapsed).max(0.0))
+    }
+}
+
+/// How many times the player has started the level, including the current attempt.
+#[derive(Resource, Default)]
+pub struct Attempts(pub u32);
+
+/// Why the last attempt failed.
+#[derive(Resource, Default)]
+pub struct FailReason(pub String);
+
+fn resume_physics(mut rapier_configuration: ResMut<RapierConfiguration>) {
+    rapier_configuration.physics_pipeline_active = true;
+}
+
+fn pause_physics(mut rapier_configuration: ResMut<RapierConfiguration>) {
+    rapier_configuration.physics_pipeline_active = false;
+}
+
+/// Every time the rig is put back at the start the player gets a fresh attempt at the level.
+pub fn start_attempt(
+    mut reset_events: EventReader<ResetRig>,
+    mut level_timer: ResMut<LevelTimer>,
+    mut attempts: ResMut<Attempts>,
+    mut objectives: ResMut<Objectives>,
+    mut parking_scores: ResMut<ParkingScores>,
+) {
+    if reset_events.iter().count() == 0 {
+        return;
+    }
+    level_timer.elapsed = 0.0;
+    attempts.0 += 1;
+    objectives.current = 0;
+    parking_scores.completed.clear();
+    info!("starting attempt {}", attempts.0);
+}
+
+fn tick_level_timer(mut level_timer: ResMut<LevelTimer>, time: Res<Time>) {
+    level_timer.elapsed += time.delta_seconds();
+}
+
+fn check_time_limit(
+    level_timer: Res<LevelTimer>,
+    mut fail_reason: ResMut<FailReason>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if level_timer.remaining() == Some(0.0) {
+        info!("ran out of time");
+        fail_reason.0 = "Ran out of time".to_string();
+        next_state.set(GameState::Failed);
+    }
+}
+
+/// Finishes the level once every objective is done and the whole rig has stopped moving in the
+/// last spot.
+fn check_level_complete(
+    objectives: Res<Objectives>,
+    parking_spots: Query<&ParkingSpot>,
+    vehicles: Query<&Velocity, Or<(With<Car>, With<Trailer>)>>,
+    level_timer: Res<LevelTimer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !objectives.is_complete() {
+        return;
+    }
+    let still_parked = objectives
+        .steps
+        .last()
+        .and_then(|spot| parking_spots.get(*spot).ok())
+        .is_none_or(ParkingSpot::is_filled);
+    if still_parked
+        && vehicles
+            .iter()
+            .all(|velocity| velocity.linvel.length() < STOPPED_SPEED)
+    {
+        info!("level complete in {:.1}s", level_timer.elapsed);
+        next_state.set(GameState::LevelComplete);
+    }
+}
+
+fn toggle_pause(
+    actions: Res<Input<Action>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+    match state.get() {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        _ => {}
+    }
+}
@@ -0,0 +1,398 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_rapier3d::{
+    prelude::*,
+    rapier::dynamics::{JointAxesMask, JointAxis},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{car::Car, controls::Action, game_state::GameState};
+
+/// How close, in meters, two free anchors have to be for the player to hitch them together.
+const COUPLING_DISTANCE: f32 = 0.75;
+/// Most a gooseneck trailer can tip nose up or down, in radians. The ball sits over the tow
+/// vehicle's rear axle, so the bed stops the trailer's neck well before a tow bar would.
+const GOOSENECK_MAX_PITCH: f32 = 0.15;
+/// Most a gooseneck trailer can lean side to side, in radians.
+const GOOSENECK_MAX_ROLL: f32 = 0.1;
+
+pub struct HitchPlugin;
+
+impl Plugin for HitchPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CoupleVehicles>()
+            .add_event::<UncoupleVehicles>()
+            .add_event::<Jackknifed>()
+            .init_resource::<ArticulationAngles>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        toggle_hitch.run_if(in_state(GameState::Playing)),
+                        uncouple_vehicles,
+                        couple_vehicles,
+                    )
+                        .chain(),
+                    draw_free_anchors,
+                    measure_articulation_angles,
+                ),
+            )
+            .register_type::<Hitch>()
+            .register_type::<HitchConfig>();
+    }
+}
+
+#[derive(Default, Reflect, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum HitchType {
+    /// A ball on the tow bar. The trailer can swing, pitch and roll within its limits.
+    #[default]
+    Ball,
+    /// A semi trailer's kingpin sitting on a turntable. It turns about the vertical axis and can
+    /// pitch a little, but never rolls relative to the tractor.
+    FifthWheel,
+    /// A ball in the tow vehicle's bed. It swings like a ball hitch, but pitch and roll are
+    /// capped at `GOOSENECK_MAX_PITCH` and `GOOSENECK_MAX_ROLL` whatever the config allows.
+    Gooseneck,
+}
+
+/// How a vehicle's front hitch is allowed to move relative to the vehicle towing it.
+///
+/// Angles are in radians and are symmetric about straight ahead.
+#[derive(Reflect, Clone, Serialize, Deserialize)]
+pub struct HitchConfig {
+    pub hitch_type: HitchType,
+    /// How far the trailer can swing left or right, about the vertical axis.
+    pub max_yaw: f32,
+    /// How far the trailer can tip nose up or down.
+    pub max_pitch: f32,
+    /// How far the trailer can lean side to side. Ignored by fifth wheels, which can't roll.
+    pub max_roll: f32,
+    /// Strength of a motor resisting any rotation about the hitch, if any.
+    pub damping: Option<f32>,
+    /// Articulation angle past which the vehicle counts as jackknifed.
+    pub jackknife_angle: f32,
+}
+
+impl Default for HitchConfig {
+    fn default() -> Self {
+        Self {
+            hitch_type: HitchType::Ball,
+            max_yaw: 1.5,
+            max_pitch: 0.35,
+            max_roll: 0.2,
+            damping: None,
+            jackknife_angle: 1.2,
+        }
+    }
+}
+
+/// Where a drivable can be hitched to others, and what it is currently hitched to.
+///
+/// The joint holding two vehicles together always lives on the rear vehicle and points at the
+/// vehicle towing it, so a chain of trailers is a chain of `ImpulseJoint`s.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Hitch {
+    /// Where a vehicle in front can hitch onto this one, relative to its center.
+    pub front_anchor: Option<Vec3>,
+    /// Where a vehicle behind can hitch onto this one, relative to its center.
+    pub rear_anchor: Option<Vec3>,
+    /// The vehicle this one is hitched behind.
+    pub towed_by: Option<Entity>,
+    /// The vehicle hitched behind this one.
+    pub towing: Option<Entity>,
+    /// How the front hitch moves once it's coupled.
+    pub config: HitchConfig,
+}
+
+/// The articulation angle at one coupled hitch: how far the rear vehicle has swung away from
+/// straight behind the vehicle towing it.
+pub struct ArticulationAngle {
+    pub front: Entity,
+    pub rear: Entity,
+    /// Radians about the vertical axis, positive when the rear vehicle is turned anticlockwise,
+    /// seen from above, relative to the vehicle towing it.
+    pub angle: f32,
+    pub jackknife_angle: f32,
+}
+
+impl ArticulationAngle {
+    pub fn is_jackknifed(&self) -> bool {
+        self.angle.abs() > self.jackknife_angle
+    }
+}
+
+/// Articulation angle of every coupled hitch in the car's rig, from the car backwards.
+#[derive(Resource, Default)]
+pub struct ArticulationAngles(pub Vec<ArticulationAngle>);
+
+/// Sent when a hitch's articulation angle goes past its jackknife angle.
+#[derive(Event)]
+pub struct Jackknifed {
+    pub front: Entity,
+    pub rear: Entity,
+    pub angle: f32,
+}
+
+/// Request to hitch `rear` behind `front`.
+#[derive(Event)]
+pub struct CoupleVehicles {
+    pub front: Entity,
+    pub rear: Entity,
+}
+
+/// Request to unhitch `rear` from whatever is towing it.
+#[derive(Event)]
+pub struct UncoupleVehicles {
+    pub rear: Entity,
+}
+
+/// Builds the joint between the front vehicle's rear anchor and the rear vehicle's front anchor.
+///
+/// Vehicles face +X with +Y up, so yaw is about the joint's Y axis, pitch about Z and roll about X.
+pub fn hitch_joint(front_anchor: Vec3, rear_anchor: Vec3, config: &HitchConfig) -> GenericJoint {
+    let yaw = (JointAxis::AngY, config.max_yaw);
+    let (max_pitch, max_roll) = match config.hitch_type {
+        HitchType::Gooseneck => (
+            config.max_pitch.min(GOOSENECK_MAX_PITCH),
+            config.max_roll.min(GOOSENECK_MAX_ROLL),
+        ),
+        _ => (config.max_pitch, config.max_roll),
+    };
+    let pitch = (JointAxis::AngZ, max_pitch);
+    let roll = (JointAxis::AngX, max_roll);
+    match config.hitch_type {
+        HitchType::Ball | HitchType::Gooseneck => {
+            let mut joint = SphericalJointBuilder::new()
+                .local_anchor1(front_anchor)
+                .local_anchor2(rear_anchor);
+            for (axis, max_angle) in [yaw, pitch, roll] {
+                joint = joint.limits(axis, [-max_angle, max_angle]);
+                if let Some(damping) = config.damping {
+                    joint = joint.motor_velocity(axis, 0.0, damping);
+                }
+            }
+            joint.into()
+        }
+        HitchType::FifthWheel => {
+            let mut joint =
+                GenericJointBuilder::new(JointAxesMask::LIN_AXES | JointAxesMask::ANG_X)
+                    .local_anchor1(front_anchor)
+                    .local_anchor2(rear_anchor);
+            for (axis, max_angle) in [yaw, pitch] {
+                joint = joint.limits(axis, [-max_angle, max_angle]);
+                if let Some(damping) = config.damping {
+                    joint = joint.motor_velocity(axis, 0.0, damping);
+                }
+            }
+            joint.into()
+        }
+    }
+}
+
+fn couple_vehicles(
+    mut commands: Commands,
+    mut couple_events: EventReader<CoupleVehicles>,
+    mut hitches: Query<&mut Hitch>,
+) {
+    for CoupleVehicles { front, rear } in couple_events.iter() {
+        let Ok([mut front_hitch, mut rear_hitch]) = hitches.get_many_mut([*front, *rear]) else {
+            warn!(
+                "can't couple {:?} to {:?}, one of them has no hitch",
+                rear, front
+            );
+            continue;
+        };
+        let (Some(front_anchor), Some(rear_anchor)) =
+            (front_hitch.rear_anchor, rear_hitch.front_anchor)
+        else {
+            warn!("can't couple {:?} to {:?}, missing an anchor", rear, front);
+            continue;
+        };
+        if front_hitch.towing.is_some() || rear_hitch.towed_by.is_some() {
+            warn!("can't couple {:?} to {:?}, already coupled", rear, front);
+            continue;
+        }
+
+        front_hitch.towing = Some(*rear);
+        rear_hitch.towed_by = Some(*front);
+        commands.entity(*rear).insert(ImpulseJoint::new(
+            *front,
+            hitch_joint(front_anchor, rear_anchor, &rear_hitch.config),
+        ));
+    }
+}
+
+fn uncouple_vehicles(
+    mut commands: Commands,
+    mut uncouple_events: EventReader<UncoupleVehicles>,
+    mut hitches: Query<&mut Hitch>,
+) {
+    for UncoupleVehicles { rear } in uncouple_events.iter() {
+        let Some(front) = hitches.get(*rear).ok().and_then(|hitch| hitch.towed_by) else {
+            continue;
+        };
+        if let Ok(mut front_hitch) = hitches.get_mut(front) {
+            front_hitch.towing = None;
+        }
+        if let Ok(mut rear_hitch) = hitches.get_mut(*rear) {
+            rear_hitch.towed_by = None;
+        }
+        commands.entity(*rear).remove::<ImpulseJoint>();
+    }
+}
+
+/// Follows the chain of hitches back from the car and returns the last vehicle in it.
+fn rearmost_vehicle(car: Entity, hitches: &Query<(Entity, &Hitch, &GlobalTransform)>) -> Entity {
+    let mut rearmost = car;
+    while let Some(next) = hitches
+        .get(rearmost)
+        .ok()
+        .and_then(|(_, hitch, _)| hitch.towing)
+    {
+        rearmost = next;
+    }
+    rearmost
+}
+
+fn world_anchor(transform: &GlobalTransform, anchor: Option<Vec3>) -> Option<Vec3> {
+    anchor.map(|anchor| transform.transform_point(anchor))
+}
+
+/// Returns the free vehicle whose front anchor is closest to `rear_anchor`, if it's close enough
+/// to hitch onto.
+fn closest_free_vehicle(
+    rear_anchor: Vec3,
+    hitches: &Query<(Entity, &Hitch, &GlobalTransform)>,
+) -> Option<Entity> {
+    hitches
+        .iter()
+        .filter(|(_, hitch, _)| hitch.towed_by.is_none())
+        .filter_map(|(entity, hitch, transform)| {
+            let front_anchor = world_anchor(transform, hitch.front_anchor)?;
+            Some((entity, front_anchor.distance(rear_anchor)))
+        })
+        .filter(|(_, distance)| *distance <= COUPLING_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
+
+/// Hitches the nearest free trailer to the back of the car's rig, or unhitches the last trailer
+/// when there's nothing close enough to hitch.
+fn toggle_hitch(
+    actions: Res<Input<Action>>,
+    car: Query<Entity, With<Car>>,
+    hitches: Query<(Entity, &Hitch, &GlobalTransform)>,
+    mut couple_events: EventWriter<CoupleVehicles>,
+    mut uncouple_events: EventWriter<UncoupleVehicles>,
+) {
+    let pressed = actions.just_pressed(Action::ToggleHitch);
+    let Ok(car) = car.get_single() else {
+        return;
+    };
+    if !pressed {
+        return;
+    }
+
+    let rearmost = rearmost_vehicle(car, &hitches);
+    let (_, rearmost_hitch, rearmost_transform) = hitches.get(rearmost).unwrap();
+    let nearby_vehicle = world_anchor(rearmost_transform, rearmost_hitch.rear_anchor)
+        .and_then(|rear_anchor| closest_free_vehicle(rear_anchor, &hitches))
+        .filter(|vehicle| *vehicle != rearmost && *vehicle != car);
+    if let Some(rear) = nearby_vehicle {
+        couple_events.send(CoupleVehicles {
+            front: rearmost,
+            rear,
+        });
+    } else if rearmost != car {
+        uncouple_events.send(UncoupleVehicles { rear: rearmost });
+    }
+}
+
+/// Marks the back of the car's rig and every free trailer's front anchor, turning them green
+/// when they're close enough to hitch.
+fn draw_free_anchors(
+    mut gizmos: Gizmos,
+    car: Query<Entity, With<Car>>,
+    hitches: Query<(Entity, &Hitch, &GlobalTransform)>,
+) {
+    let Ok(car) = car.get_single() else {
+        return;
+    };
+    let (_, rearmost_hitch, rearmost_transform) =
+        hitches.get(rearmost_vehicle(car, &hitches)).unwrap();
+    let Some(rear_anchor) = world_anchor(rearmost_transform, rearmost_hitch.rear_anchor) else {
+        return;
+    };
+    let in_range = closest_free_vehicle(rear_anchor, &hitches);
+    let mut any_free_vehicles = false;
+    for (entity, hitch, transform) in &hitches {
+        if hitch.towed_by.is_some() || entity == car {
+            continue;
+        }
+        if let Some(front_anchor) = world_anchor(transform, hitch.front_anchor) {
+            let color = if in_range == Some(entity) {
+                Color::GREEN
+            } else {
+                Color::YELLOW
+            };
+            gizmos.sphere(front_anchor, Quat::IDENTITY, 0.2, color);
+            any_free_vehicles = true;
+        }
+    }
+    if any_free_vehicles {
+        gizmos.sphere(rear_anchor, Quat::IDENTITY, 0.2, Color::BLUE);
+    }
+}
+
+/// Heading of a vehicle about the vertical axis, in radians from +X.
+fn heading(transform: &GlobalTransform) -> f32 {
+    let forward = transform.right();
+    (-forward.z).atan2(forward.x)
+}
+
+/// Wraps an angle in radians into `-PI..=PI`.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+fn measure_articulation_angles(
+    car: Query<Entity, With<Car>>,
+    hitches: Query<(Entity, &Hitch, &GlobalTransform)>,
+    mut articulation_angles: ResMut<ArticulationAngles>,
+    mut jackknifed_events: EventWriter<Jackknifed>,
+) {
+    let mut angles = Vec::new();
+    if let Ok(car) = car.get_single() {
+        let mut front = car;
+        while let Ok((_, front_hitch, front_transform)) = hitches.get(front) {
+            let Some((rear, rear_hitch, rear_transform)) =
+                front_hitch.towing.and_then(|rear| hitches.get(rear).ok())
+            else {
+                break;
+            };
+            let articulation_angle = ArticulationAngle {
+                front,
+                rear,
+                angle: wrap_angle(heading(rear_transform) - heading(front_transform)),
+                jackknife_angle: rear_hitch.config.jackknife_angle,
+            };
+
+            let was_jackknifed = articulation_angles
+                .0
+                .iter()
+                .any(|previous| previous.rear == rear && previous.is_jackknifed());
+            if articulation_angle.is_jackknifed() && !was_jackknifed {
+                jackknifed_events.send(Jackknifed {
+                    front,
+                    rear,
+                    angle: articulation_angle.angle,
+                });
+            }
+            angles.push(articulation_angle);
+            front = rear;
+        }
+    }
+    articulation_angles.0 = angles;
+}
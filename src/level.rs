@@ -0,0 +1,742 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::{BoxedFuture, HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car::{self, SpawnPoint, VehicleConfig, VehiclePose},
+    car_configs::VehicleConfigs,
+    checkpoint,
+    editor::Editable,
+    game_state::{start_attempt, Attempts, LevelTimer},
+    parking_spot::{self, Objectives, ParkingSpot, RequiredVehicle},
+    penalty::PenaltyRules,
+};
+
+/// Names of the level definitions under `assets/levels/`, loaded as `<name>.level.ron`, in the
+/// order the player unlocks them.
+///
+/// Listed explicitly for the same reason as `car_configs::VEHICLE_NAMES`.
+const LEVEL_NAMES: [&str; 4] = ["first_reverse", "loading_docks", "road_train", "pick_up"];
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<LevelDefinition>()
+            .init_asset_loader::<LevelDefinitionLoader>()
+            .add_event::<ChangeLevel>()
+            .init_resource::<Levels>()
+            .init_resource::<CurrentLevel>()
+            .register_type::<LevelEntity>()
+            .add_systems(Startup, load_levels)
+            .add_systems(
+                Update,
+                (
+                    change_level.before(start_attempt),
+                    hot_reload_level,
+                    spawn_level,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Everything that makes up a level, loaded from a `.level.ron` file in `assets/levels/`.
+///
+/// Headings are in radians about the vertical axis, with 0 facing +X.
+#[derive(Clone, Serialize, Deserialize, TypePath, TypeUuid)]
+#[uuid = "b3f1c9d2-6a4e-4f0b-8c7d-2e5a9f1b3c64"]
+pub struct LevelDefinition {
+    pub name: String,
+    /// glTF scene the level is built around. Its meshes are turned into static colliders.
+    #[serde(default)]
+    pub scene: Option<SceneDefinition>,
+    /// Immovable boxes such as walls.
+    #[serde(default)]
+    pub static_colliders: Vec<BoxDefinition>,
+    /// Boxes that can be pushed around, such as cones and crates.
+    #[serde(default)]
+    pub props: Vec<PropDefinition>,
+    pub rig: RigDefinition,
+    /// Vehicles parked around the level on their own, for the player to hitch up during play.
+    #[serde(default)]
+    pub free_vehicles: Vec<FreeVehicleDefinition>,
+    pub parking_spots: Vec<ParkingSpotDefinition>,
+    /// Names of the parking spots the player has to fill, in order.
+    pub objectives: Vec<String>,
+    /// Places the rig can be put back to after the car has driven through them.
+    #[serde(default)]
+    pub checkpoints: Vec<CheckpointDefinition>,
+    /// Seconds the player has to finish the level, if there's a limit.
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+    #[serde(default)]
+    pub penalties: PenaltyRules,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneDefinition {
+    /// Asset path of the scene, e.g. `"track.glb#Scene0"`.
+    pub path: String,
+    pub translation: Vec3,
+    #[serde(default)]
+    pub heading: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BoxDefinition {
+    pub name: String,
+    /// Center of the box.
+    pub translation: Vec3,
+    #[serde(default)]
+    pub heading: f32,
+    pub half_extents: Vec3,
+    /// Image in `assets/` to draw on the box. Boxes without one are plain white.
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PropDefinition {
+    pub shape: BoxDefinition,
+    /// Repeats the prop in a wall, instead of placing just one.
+    #[serde(default)]
+    pub stack: Option<PropStack>,
+}
+
+/// A wall of identical props, starting at the prop's translation and growing sideways and up.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PropStack {
+    /// Number of props side by side, along the prop's local Z axis.
+    pub columns: u32,
+    /// Number of props stacked on top of each other.
+    pub rows: u32,
+    /// Meters between the centers of neighbouring props.
+    pub spacing: f32,
+}
+
+/// The player's car and trailers, and where they start.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RigDefinition {
+    /// Names of the vehicle definitions in `assets/vehicles/`, starting with the car and followed
+    /// by each trailer hitched behind it, e.g. `["car", "dolly", "trailer"]`.
+    pub vehicles: Vec<String>,
+    pub translation: Vec3,
+    #[serde(default)]
+    pub heading: f32,
+    /// See `SpawnPoint::hitch_angles`.
+    #[serde(default)]
+    pub hitch_angles: Vec<f32>,
+}
+
+/// A vehicle that starts unhitched.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FreeVehicleDefinition {
+    /// Name of the vehicle definition in `assets/vehicles/`, e.g. `"trailer"`.
+    pub vehicle: String,
+    /// Center of the vehicle on the ground. It's lifted by its own ride height, like the rig.
+    pub translation: Vec3,
+    #[serde(default)]
+    pub heading: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ParkingSpotDefinition {
+    pub name: String,
+    /// Center of the spot.
+    pub translation: Vec3,
+    #[serde(default)]
+    pub heading: f32,
+    /// Full length, height and width of the spot.
+    pub size: Vec3,
+    #[serde(default)]
+    pub required_vehicle: RequiredVehicle,
+    /// See `ParkingSpot::heading_tolerance`.
+    pub heading_tolerance: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CheckpointDefinition {
+    pub name: String,
+    /// Center of the trigger.
+    pub translation: Vec3,
+    #[serde(default)]
+    pub heading: f32,
+    /// Full length, height and width of the trigger.
+    pub size: Vec3,
+}
+
+/// Marks everything spawned from the current level, so it can all be despawned together.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct LevelEntity;
+
+pub struct LevelInfo {
+    /// Name of the level's file, which identifies it in saved progress.
+    pub file_name: String,
+    pub handle: Handle<LevelDefinition>,
+}
+
+/// Every level, in the order the player unlocks them.
+#[derive(Resource, Default)]
+pub struct Levels(pub Vec<LevelInfo>);
+
+impl Levels {
+    /// The level's display name, or its file name if it hasn't loaded yet.
+    pub fn display_name<'a>(
+        &'a self,
+        index: usize,
+        level_assets: &'a Assets<LevelDefinition>,
+    ) -> &'a str {
+        let level_info = &self.0[index];
+        level_assets
+            .get(&level_info.handle)
+            .map_or(&level_info.file_name, |level| &level.name)
+    }
+}
+
+/// The level being played.
+#[derive(Resource, Default)]
+pub struct CurrentLevel {
+    /// Index into `Levels`.
+    pub index: usize,
+    /// Whether the level's entities have been spawned yet.
+    pub spawned: bool,
+}
+
+/// Despawns the current level and replaces it with another one.
+#[derive(Event)]
+pub struct ChangeLevel {
+    pub index: usize,
+}
+
+#[derive(Default)]
+struct LevelDefinitionLoader;
+
+impl AssetLoader for LevelDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level: LevelDefinition = ron::de::from_bytes(bytes)?;
+            if let Err(message) = level.validate() {
+                return Err(bevy::asset::Error::msg(format!(
+                    "invalid level {:?}: {}",
+                    load_context.path(),
+                    message
+                )));
+            }
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+impl LevelDefinition {
+    /// Checks that every box has a size, parking spots and checkpoints have unique names, and
+    /// every objective names a parking spot.
+    pub fn validate(&self) -> Result<(), String> {
+        let boxes = self
+            .static_colliders
+            .iter()
+            .chain(self.props.iter().map(|prop| &prop.shape));
+        for shape in boxes {
+            if !(shape.half_extents.is_finite() && shape.half_extents.min_element() > 0.0) {
+                return Err(format!("{} must have a size greater than 0", shape.name));
+            }
+        }
+        if self.rig.vehicles.is_empty() {
+            return Err("the rig needs at least one vehicle".to_string());
+        }
+        if self.rig.hitch_angles.len() >= self.rig.vehicles.len() {
+            return Err("there's a hitch angle for every trailer at most".to_string());
+        }
+        if let Some(angle) = self
+            .rig
+            .hitch_angles
+            .iter()
+            .find(|angle| !(-PI / 2.0..=PI / 2.0).contains(*angle))
+        {
+            return Err(format!(
+                "hitch angles must be between -pi/2 and pi/2, got {}",
+                angle
+            ));
+        }
+        for spot in &self.parking_spots {
+            if !(spot.size.is_finite() && spot.size.min_element() > 0.0) {
+                return Err(format!("{} must have a size greater than 0", spot.name));
+            }
+            if !(0.0..=PI / 2.0).contains(&spot.heading_tolerance) {
+                return Err(format!(
+                    "{} heading_tolerance must be between 0 and pi/2, got {}",
+                    spot.name, spot.heading_tolerance
+                ));
+            }
+        }
+        for checkpoint in &self.checkpoints {
+            if !(checkpoint.size.is_finite() && checkpoint.size.min_element() > 0.0) {
+                return Err(format!(
+                    "{} must have a size greater than 0",
+                    checkpoint.name
+                ));
+            }
+        }
+        check_names(
+            "parking spot",
+            self.parking_spots.iter().map(|spot| spot.name.as_str()),
+        )?;
+        check_names(
+            "checkpoint",
+            self.checkpoints
+                .iter()
+                .map(|checkpoint| checkpoint.name.as_str()),
+        )?;
+        for objective in &self.objectives {
+            if !self
+                .parking_spots
+                .iter()
+                .any(|spot| &spot.name == objective)
+            {
+                return Err(format!("objective {} isn't a parking spot", objective));
+            }
+        }
+        if self.time_limit.is_some_and(|time_limit| time_limit <= 0.0) {
+            return Err("time_limit must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Checks that every name is filled in and used only once, since spots are looked up by name.
+fn check_names<'a>(kind: &str, names: impl Iterator<Item = &'a str>) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if name.trim().is_empty() {
+            return Err(format!("every {} needs a name", kind));
+        }
+        if !seen.insert(name) {
+            return Err(format!("there's more than one {} named {}", kind, name));
+        }
+    }
+    Ok(())
+}
+
+pub fn pose(translation: Vec3, heading: f32) -> Transform {
+    Transform::from_translation(translation).with_rotation(Quat::from_rotation_y(heading))
+}
+
+/// Path of a level's file, relative to `assets/`.
+pub fn level_path(file_name: &str) -> String {
+    format!("levels/{}.level.ron", file_name)
+}
+
+fn load_levels(asset_server: Res<AssetServer>, mut levels: ResMut<Levels>) {
+    levels.0 = LEVEL_NAMES
+        .iter()
+        .map(|name| LevelInfo {
+            file_name: name.to_string(),
+            handle: asset_server.load(level_path(name)),
+        })
+        .collect();
+}
+
+fn change_level(
+    mut commands: Commands,
+    mut change_level_events: EventReader<ChangeLevel>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut attempts: ResMut<Attempts>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+) {
+    let Some(ChangeLevel { index }) = change_level_events.iter().last() else {
+        return;
+    };
+    if *index == current_level.index {
+        return;
+    }
+    despawn_level(&mut commands, &level_entities);
+    *current_level = CurrentLevel {
+        index: *index,
+        spawned: false,
+    };
+    attempts.0 = 0;
+}
+
+/// Despawns the current level so it's spawned again from the edited file.
+fn hot_reload_level(
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<LevelDefinition>>,
+    levels: Res<Levels>,
+    mut current_level: ResMut<CurrentLevel>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+) {
+    let Some(current_level_info) = levels.0.get(current_level.index) else {
+        return;
+    };
+    for event in asset_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if *handle == current_level_info.handle && current_level.spawned {
+                info!("reloading level");
+                despawn_level(&mut commands, &level_entities);
+                current_level.spawned = false;
+            }
+        }
+    }
+}
+
+pub fn despawn_level(commands: &mut Commands, level_entities: &Query<Entity, With<LevelEntity>>) {
+    for entity in level_entities {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn box_material(
+    shape: &BoxDefinition,
+    asset_server: &AssetServer,
+    materials: &mut Assets<StandardMaterial>,
+) -> Handle<StandardMaterial> {
+    materials.add(StandardMaterial {
+        base_color_texture: shape
+            .texture
+            .as_ref()
+            .map(|texture| asset_server.load(texture.as_str())),
+        unlit: false,
+        ..default()
+    })
+}
+
+fn box_mesh(shape: &BoxDefinition, meshes: &mut Assets<Mesh>) -> Handle<Mesh> {
+    let half_extents = shape.half_extents;
+    meshes.add(Mesh::from(shape::Box {
+        min_x: -half_extents.x,
+        max_x: half_extents.x,
+        min_y: -half_extents.y,
+        max_y: half_extents.y,
+        min_z: -half_extents.z,
+        max_z: half_extents.z,
+    }))
+}
+
+pub fn spawn_static_collider(
+    commands: &mut Commands,
+    shape: &BoxDefinition,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    asset_server: &AssetServer,
+) -> Entity {
+    commands
+        .spawn((
+            MaterialMeshBundle {
+                mesh: box_mesh(shape, meshes),
+                material: box_material(shape, asset_server, materials),
+                transform: pose(shape.translation, shape.heading),
+                ..default()
+            },
+            RigidBody::Fixed,
+            Collider::cuboid(
+                shape.half_extents.x,
+                shape.half_extents.y,
+                shape.half_extents.z,
+            ),
+            Friction::coefficient(1.0),
+            Editable::StaticCollider(shape.clone()),
+            LevelEntity,
+            Name::from(shape.name.as_str()),
+        ))
+        .id()
+}
+
+/// Spawns a prop, or a stack of them, under a parent entity that moves them all together.
+pub fn spawn_prop(
+    commands: &mut Commands,
+    prop: &PropDefinition,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    asset_server: &AssetServer,
+) -> Entity {
+    let shape = &prop.shape;
+    let mesh = box_mesh(shape, meshes);
+    let material = box_material(shape, asset_server, materials);
+    let spawn_one = |child_builder: &mut ChildBuilder, translation: Vec3, name: String| {
+        child_builder.spawn((
+            MaterialMeshBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(translation),
+                ..default()
+            },
+            RigidBody::Dynamic,
+            Collider::cuboid(
+                shape.half_extents.x,
+                shape.half_extents.y,
+                shape.half_extents.z,
+            ),
+            Friction::coefficient(1.0),
+            Name::from(name),
+        ));
+    };
+    commands
+        .spawn((
+            SpatialBundle::from_transform(pose(shape.translation, shape.heading)),
+            Editable::Prop(prop.clone()),
+            LevelEntity,
+            Name::from(shape.name.as_str()),
+        ))
+        .with_children(|child_builder| match &prop.stack {
+            None => spawn_one(child_builder, Vec3::ZERO, shape.name.clone()),
+            Some(stack) => {
+                for column in 0..stack.columns {
+                    for row in 0..stack.rows {
+                        spawn_one(
+                            child_builder,
+                            Vec3::new(
+                                0.0,
+                                row as f32 * stack.spacing,
+                                column as f32 * stack.spacing,
+                            ),
+                            format!("{} ({},{})", shape.name, column, row),
+                        );
+                    }
+                }
+            }
+        })
+        .id()
+}
+
+pub fn spawn_level_parking_spot(commands: &mut Commands, spot: &ParkingSpotDefinition) -> Entity {
+    let entity = parking_spot::spawn_parking_spot(
+        commands,
+        &spot.name,
+        pose(spot.translation, spot.heading).with_scale(spot.size),
+        ParkingSpot::new(spot.required_vehicle, spot.heading_tolerance),
+    );
+    commands.entity(entity).insert((
+        Editable::ParkingSpot {
+            loaded_as: Some(spot.name.clone()),
+        },
+        LevelEntity,
+    ));
+    entity
+}
+
+pub fn spawn_level_checkpoint(
+    commands: &mut Commands,
+    checkpoint: &CheckpointDefinition,
+) -> Entity {
+    let entity = checkpoint::spawn_checkpoint(
+        commands,
+        &checkpoint.name,
+        pose(checkpoint.translation, checkpoint.heading).with_scale(checkpoint.size),
+    );
+    commands
+        .entity(entity)
+        .insert((Editable::Checkpoint, LevelEntity));
+    entity
+}
+
+/// Marks where the rig starts, with a sensor so the editor can pick it up and move it.
+fn spawn_spawn_point(
+    commands: &mut Commands,
+    rig: &RigDefinition,
+    vehicles: Vec<Entity>,
+    free_vehicles: Vec<VehiclePose>,
+) -> Entity {
+    commands
+        .spawn((
+            TransformBundle::from_transform(pose(rig.translation, rig.heading)),
+            Collider::cuboid(0.5, 0.5, 0.5),
+            Sensor,
+            SpawnPoint {
+                vehicles,
+                hitch_angles: rig.hitch_angles.clone(),
+                free_vehicles,
+            },
+            Editable::SpawnPoint,
+            LevelEntity,
+            Name::from("Spawn Point"),
+        ))
+        .id()
+}
+
+/// Spawns the current level once it and all of its vehicles have loaded.
+#[allow(clippy::too_many_arguments)]
+fn spawn_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    levels: Res<Levels>,
+    mut current_level: ResMut<CurrentLevel>,
+    level_assets: Res<Assets<LevelDefinition>>,
+    vehicle_configs: Res<VehicleConfigs>,
+    vehicle_config_assets: Res<Assets<VehicleConfig>>,
+    mut objectives: ResMut<Objectives>,
+    mut level_timer: ResMut<LevelTimer>,
+    mut penalty_rules: ResMut<PenaltyRules>,
+) {
+    if current_level.spawned {
+        return;
+    }
+    let Some(level) = levels
+        .0
+        .get(current_level.index)
+        .and_then(|level_info| level_assets.get(&level_info.handle))
+    else {
+        return;
+    };
+    let free_vehicle_names = level
+        .free_vehicles
+        .iter()
+        .map(|free_vehicle| &free_vehicle.vehicle);
+    if let Some(missing) = level
+        .rig
+        .vehicles
+        .iter()
+        .chain(free_vehicle_names.clone())
+        .find(|name| vehicle_configs.handle(name).is_none())
+    {
+        error!("level {} uses unknown vehicle {}", level.name, missing);
+        current_level.spawned = true;
+        return;
+    }
+    let Some(rig_configs) = level
+        .rig
+        .vehicles
+        .iter()
+        .map(|name| vehicle_configs.get(name, &vehicle_config_assets).cloned())
+        .collect::<Option<Vec<VehicleConfig>>>()
+    else {
+        return;
+    };
+    let Some(free_vehicle_configs) = free_vehicle_names
+        .map(|name| vehicle_configs.get(name, &vehicle_config_assets).cloned())
+        .collect::<Option<Vec<VehicleConfig>>>()
+    else {
+        return;
+    };
+    info!("spawning level {}", level.name);
+    current_level.spawned = true;
+
+    if let Some(scene) = &level.scene {
+        commands.spawn((
+            SceneBundle {
+                scene: asset_server.load(scene.path.as_str()),
+                transform: pose(scene.translation, scene.heading),
+                ..default()
+            },
+            AsyncSceneCollider::default(),
+            LevelEntity,
+            Name::from("Level"),
+        ));
+    }
+
+    for shape in &level.static_colliders {
+        spawn_static_collider(
+            &mut commands,
+            shape,
+            &mut meshes,
+            &mut materials,
+            &asset_server,
+        );
+    }
+    for prop in &level.props {
+        spawn_prop(
+            &mut commands,
+            prop,
+            &mut meshes,
+            &mut materials,
+            &asset_server,
+        );
+    }
+    let tire_material = materials.add(StandardMaterial {
+        base_color: Color::BLACK,
+        ..default()
+    });
+    let rig_entities = car::spawn_rig(
+        &mut commands,
+        &rig_configs,
+        pose(level.rig.translation, level.rig.heading),
+        &level.rig.hitch_angles,
+        &mut meshes,
+        tire_material.clone(),
+        &asset_server,
+    );
+    for (entity, name) in rig_entities.iter().zip(&level.rig.vehicles) {
+        commands
+            .entity(*entity)
+            .insert((vehicle_configs.handle(name).unwrap().clone(), LevelEntity));
+    }
+    let mut free_vehicles = Vec::new();
+    for (i, (free_vehicle, vehicle_config)) in level
+        .free_vehicles
+        .iter()
+        .zip(free_vehicle_configs)
+        .enumerate()
+    {
+        let transform = pose(
+            free_vehicle.translation + Vec3::Y * vehicle_config.height,
+            free_vehicle.heading,
+        );
+        let entity = car::spawn_vehicle(
+            &mut commands,
+            vehicle_config,
+            transform,
+            &mut meshes,
+            tire_material.clone(),
+            &format!("Free Vehicle {}", i + 1),
+            false,
+            &asset_server,
+        );
+        commands.entity(entity).insert((
+            vehicle_configs
+                .handle(&free_vehicle.vehicle)
+                .unwrap()
+                .clone(),
+            LevelEntity,
+        ));
+        free_vehicles.push(VehiclePose {
+            vehicle: entity,
+            transform,
+            towed_by: None,
+        });
+    }
+    spawn_spawn_point(&mut commands, &level.rig, rig_entities, free_vehicles);
+
+    let spot_entities: HashMap<&str, Entity> = level
+        .parking_spots
+        .iter()
+        .map(|spot| {
+            (
+                spot.name.as_str(),
+                spawn_level_parking_spot(&mut commands, spot),
+            )
+        })
+        .collect();
+    for checkpoint in &level.checkpoints {
+        spawn_level_checkpoint(&mut commands, checkpoint);
+    }
+    *objectives = Objectives {
+        steps: level
+            .objectives
+            .iter()
+            .map(|name| spot_entities[name.as_str()])
+            .collect(),
+        current: 0,
+    };
+
+    level_timer.elapsed = 0.0;
+    level_timer.time_limit = level.time_limit;
+    *penalty_rules = level.penalties.clone();
+}
@@ -1,8 +1,25 @@
+#![allow(clippy::type_complexity)]
+
 mod car;
 mod car_configs;
+mod checkpoint;
+mod controls;
+mod drivetrain;
+mod editor;
+mod game_state;
+mod hitch;
+mod level;
 mod parking_spot;
+mod penalty;
+mod presets;
+mod progress;
+mod score;
+mod storage;
 mod ui;
 
+use std::time::Duration;
+
+use bevy::asset::ChangeWatcher;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::input::common_conditions::input_toggle_active;
 use bevy::prelude::*;
@@ -18,22 +35,40 @@ fn main() {
             0xFF as f32 / 255.0,
         )))
         .add_plugins((
-            DefaultPlugins,
+            DefaultPlugins.set(AssetPlugin {
+                watch_for_changes: ChangeWatcher::with_delay(Duration::from_millis(200)),
+                ..default()
+            }),
             RapierPhysicsPlugin::<NoUserData>::default(),
             // RapierDebugRenderPlugin::default(),
         ))
         .add_plugins((
             LogDiagnosticsPlugin::default(),
-            FrameTimeDiagnosticsPlugin::default(),
-            WorldInspectorPlugin::default().run_if(input_toggle_active(false, KeyCode::Escape)),
+            FrameTimeDiagnosticsPlugin,
+            WorldInspectorPlugin::default()
+                .run_if(input_toggle_active(false, controls::Action::ToggleSettings)),
         ))
         .add_plugins((
             car::CarPlugin,
+            car_configs::CarConfigsPlugin,
+            checkpoint::CheckpointPlugin,
+            controls::ControlsPlugin,
+            drivetrain::DrivetrainPlugin,
+            editor::EditorPlugin,
+            game_state::GameStatePlugin,
+            hitch::HitchPlugin,
+            level::LevelPlugin,
             ui::UIPlugin,
             parking_spot::ParkingSpotPlugin,
+            score::ScorePlugin,
+            penalty::PenaltyPlugin,
+            progress::ProgressPlugin,
         ))
-        .add_systems(Startup, setup_physics)
-        .add_systems(Update, (camera_follow_car, set_transform_on_level))
+        .add_systems(Startup, setup_camera_and_sun)
+        .add_systems(
+            Update,
+            camera_follow_car.run_if(not(in_state(game_state::GameState::Editing))),
+        )
         .run();
 }
 
@@ -41,16 +76,7 @@ fn main() {
 #[reflect(Component)]
 struct CarCamera;
 
-#[derive(Component, Default, Reflect)]
-#[reflect(Component)]
-struct Level;
-
-pub fn setup_physics(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
+fn setup_camera_and_sun(mut commands: Commands) {
     // camera
     commands.spawn((
         Camera3dBundle {
@@ -87,107 +113,6 @@ pub fn setup_physics(
         },
         Name::from("Sun"),
     ));
-
-    commands.spawn((
-        SceneBundle {
-            scene: asset_server.load("low_poly_race_track.glb#Scene0"),
-            transform: Transform::from_xyz(0.0, -10.0, 30.0),
-            ..default()
-        },
-        AsyncSceneCollider::default(),
-        Level,
-        Name::from("Level"),
-    ));
-
-    let tire_material = materials.add(StandardMaterial {
-        base_color: Color::BLACK,
-        ..default()
-    });
-    // car and trailer
-    let car_config = car_configs::CAR_CONFIG;
-    let car_entity = car::spawn_vehicle(
-        &mut commands,
-        car_config.clone(),
-        &mut meshes,
-        tire_material.clone(),
-        "Car",
-        true,
-        &asset_server,
-    );
-
-    let trailer_config = car_configs::TRAILER_CONFIG;
-    let trailer_entity = car::spawn_vehicle(
-        &mut commands,
-        trailer_config.clone(),
-        &mut meshes,
-        tire_material.clone(),
-        "Trailer",
-        false,
-        &asset_server,
-    );
-
-    let joint = SphericalJointBuilder::new()
-        .local_anchor1(car_config.anchor_point)
-        .local_anchor2(trailer_config.anchor_point);
-    commands
-        .get_entity(trailer_entity)
-        .unwrap()
-        .insert(ImpulseJoint::new(car_entity, joint));
-
-    let floor_texture_handle = asset_server.load("floor.png");
-    let wx = 0.5;
-    let wy = 4.0;
-    let wz = 15.0;
-    commands.spawn((
-        MaterialMeshBundle {
-            mesh: meshes.add(Mesh::from(shape::Box {
-                min_x: -wx,
-                max_x: wx,
-                min_y: -wy,
-                max_y: wy,
-                min_z: -wz,
-                max_z: wz,
-            })),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(floor_texture_handle.clone()),
-                unlit: false,
-                ..default()
-            }),
-            transform: Transform::from_xyz(-15.0, -5.0, -5.0),
-            ..default()
-        },
-        RigidBody::Fixed,
-        Collider::cuboid(wx, wy, wz),
-        Friction::coefficient(1.0),
-        Name::from("Wall"),
-    ));
-
-    // add boxes to run into
-    let mut box_parent_entity = commands.spawn((SpatialBundle::default(), Name::from("Obstacles")));
-    let w = 10;
-    let h = 5;
-    for x in (-w / 2)..(w / 2) {
-        for y in 0..h {
-            box_parent_entity.with_children(|child_builder| {
-                child_builder.spawn((
-                    MaterialMeshBundle {
-                        mesh: meshes.add(Mesh::from(shape::Cube { size: 0.5 * 2.0 })),
-                        material: materials.add(StandardMaterial {
-                            base_color_texture: Some(floor_texture_handle.clone()),
-                            unlit: false,
-                            ..default()
-                        }),
-                        transform: Transform::from_xyz(15.0, (y as f32) * 1.5, (x as f32) * 1.5),
-                        ..default()
-                    },
-                    RigidBody::Dynamic,
-                    Collider::cuboid(0.5, 0.5, 0.5),
-                    Friction::coefficient(1.0),
-                    Name::from(format!("Box ({},{})", x, y)),
-                ));
-            });
-        }
-    }
 }
 
 fn camera_follow_car(
@@ -196,23 +121,15 @@ fn camera_follow_car(
     car: Query<&GlobalTransform, With<Car>>,
     time: Res<Time>,
 ) {
-    let new_cam_location = car_camera_desired_position.single();
+    let (Ok(new_cam_location), Ok(car)) =
+        (car_camera_desired_position.get_single(), car.get_single())
+    else {
+        return;
+    };
     let mut car_camera = camera.single_mut();
     let lerped_position = car_camera
         .translation
         .lerp(new_cam_location.translation(), time.delta_seconds());
     car_camera.translation = Vec3::new(lerped_position.x, lerped_position.y, lerped_position.z);
-    car_camera.rotation = car_camera
-        .looking_at(car.single().translation(), Vec3::Y)
-        .rotation;
-}
-
-fn set_transform_on_level(
-    mut level_transform: Query<&mut Transform, With<Level>>,
-    time: Res<Time>,
-) {
-    if time.elapsed_seconds() < 10.0 {
-        let mut level_transform = level_transform.single_mut();
-        level_transform.translation = level_transform.translation;
-    }
+    car_camera.rotation = car_camera.looking_at(car.translation(), Vec3::Y).rotation;
 }
@@ -1,90 +1,229 @@
-use bevy::prelude::*;
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::car::{Car, Trailer, VehicleCornerCollider};
 
-use crate::car::VehicleCornerCollider;
+/// Speed, in m/s, below which a vehicle counts as stopped.
+pub const STOPPED_SPEED: f32 = 0.3;
 
 pub struct ParkingSpotPlugin;
 
 impl Plugin for ParkingSpotPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<ParkingSpot>()
-            .register_type::<ParkingSpotInfo>()
-            .insert_resource(ParkingSpotInfo {
-                transform: Transform::from_scale(Vec3 {
-                    x: 10.0,
-                    y: 1.5,
-                    z: 4.0,
-                })
-                .with_translation(Vec3 {
-                    x: -22.0,
-                    y: -7.0,
-                    z: 0.0,
-                }),
-                trailer_tires_in: 0,
-            })
-            .add_systems(Startup, spawn_parking_spot)
+            .register_type::<RequiredVehicle>()
+            .add_event::<ObjectiveCompleted>()
+            .init_resource::<Objectives>()
             .add_systems(
                 Update,
-                (draw_parking_spot, check_if_trailer_in_parking_spot),
+                (
+                    update_parking_spot_occupancy,
+                    advance_objectives,
+                    draw_parking_spot,
+                )
+                    .chain(),
             );
     }
 }
 
+/// Which part of the rig has to be inside a parking spot to fill it.
+#[derive(Default, Reflect, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RequiredVehicle {
+    Car,
+    #[default]
+    Trailer,
+    Both,
+}
+
 #[derive(Component, Default, Reflect)]
 #[reflect(Component)]
-pub struct ParkingSpot;
-
-#[derive(Resource, Default, Reflect)]
-#[reflect(Resource)]
-pub struct ParkingSpotInfo {
-    transform: Transform,
-    trailer_tires_in: i32,
+pub struct ParkingSpot {
+    pub required_vehicle: RequiredVehicle,
+    /// How far, in radians, the vehicle may be turned from the spot's long axis. Either direction
+    /// along the axis counts, so vehicles can be parked nose in or nose out.
+    pub heading_tolerance: f32,
+    /// Every `VehicleCornerCollider` currently inside the spot.
+    #[reflect(ignore)]
+    pub contained_corners: HashSet<Entity>,
+    /// Whether the car is completely inside the spot and lined up with it.
+    car_in: bool,
+    /// Whether any trailer is completely inside the spot and lined up with it.
+    trailer_in: bool,
 }
 
-fn spawn_parking_spot(mut commands: Commands, parking_spot_info: Res<ParkingSpotInfo>) {
-    commands.spawn((
-        TransformBundle {
-            local: parking_spot_info.transform,
+impl ParkingSpot {
+    pub fn new(required_vehicle: RequiredVehicle, heading_tolerance: f32) -> Self {
+        Self {
+            required_vehicle,
+            heading_tolerance,
             ..default()
-        },
-        Collider::cuboid(0.5, 0.5, 0.5),
-        Sensor,
-        ActiveEvents::COLLISION_EVENTS,
-        Name::from("Parking Spot"),
-    ));
+        }
+    }
+
+    /// Whether the required vehicles are completely inside the spot and lined up with it.
+    pub fn is_filled(&self) -> bool {
+        match self.required_vehicle {
+            RequiredVehicle::Car => self.car_in,
+            RequiredVehicle::Trailer => self.trailer_in,
+            RequiredVehicle::Both => self.car_in && self.trailer_in,
+        }
+    }
 }
 
-fn draw_parking_spot(mut gizmos: Gizmos, parking_spot_info: Res<ParkingSpotInfo>) {
-    gizmos.cuboid(
-        parking_spot_info.transform,
-        if parking_spot_info.trailer_tires_in == 4 {
+/// Sent when the player fills the parking spot they were working on and stops in it.
+#[derive(Event)]
+pub struct ObjectiveCompleted {
+    pub spot: Entity,
+}
+
+/// The parking spots the player has to fill, in order.
+#[derive(Resource, Default)]
+pub struct Objectives {
+    pub steps: Vec<Entity>,
+    /// Index into `steps` of the spot the player is currently parking in.
+    pub current: usize,
+}
+
+impl Objectives {
+    pub fn current_spot(&self) -> Option<Entity> {
+        self.steps.get(self.current).copied()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.current >= self.steps.len()
+    }
+}
+
+pub fn spawn_parking_spot(
+    commands: &mut Commands,
+    name: &str,
+    transform: Transform,
+    parking_spot: ParkingSpot,
+) -> Entity {
+    commands
+        .spawn((
+            TransformBundle {
+                local: transform,
+                ..default()
+            },
+            parking_spot,
+            Collider::cuboid(0.5, 0.5, 0.5),
+            Sensor,
+            Name::from(name),
+        ))
+        .id()
+}
+
+fn draw_parking_spot(
+    mut gizmos: Gizmos,
+    parking_spots: Query<(Entity, &GlobalTransform, &ParkingSpot)>,
+    objectives: Res<Objectives>,
+) {
+    for (spot_entity, spot_transform, parking_spot) in &parking_spots {
+        let color = if objectives.current_spot() != Some(spot_entity) {
+            Color::GRAY
+        } else if parking_spot.is_filled() {
             Color::GREEN
         } else {
             Color::RED
-        },
-    );
+        };
+        gizmos.cuboid(spot_transform.compute_transform(), color);
+    }
 }
 
-fn check_if_trailer_in_parking_spot(
-    mut collision_events: EventReader<CollisionEvent>,
-    tire_colliders: Query<&VehicleCornerCollider>,
-    mut parking_spot_status: ResMut<ParkingSpotInfo>,
+/// Angle between two directions on the ground, ignoring which way along the axis each points.
+pub fn axis_angle(a: Vec3, b: Vec3) -> f32 {
+    let angle = Vec2::new(a.x, a.z).angle_between(Vec2::new(b.x, b.z)).abs();
+    angle.min(PI - angle)
+}
+
+/// Rebuilds which vehicle corners are inside each parking spot from the sensors that are
+/// currently intersecting, then works out which vehicles are fully inside and lined up.
+fn update_parking_spot_occupancy(
+    rapier_context: Res<RapierContext>,
+    mut parking_spots: Query<(&GlobalTransform, &mut ParkingSpot)>,
+    corners: Query<&Parent, With<VehicleCornerCollider>>,
+    vehicles: Query<(&GlobalTransform, Option<&Car>, Option<&Trailer>)>,
 ) {
-    for collision_event in collision_events.iter() {
-        match collision_event {
-            CollisionEvent::Started(_, collided_entity, _) => {
-                if let Ok(tire_collider) = tire_colliders.get(*collided_entity) {
-                    if !tire_collider.is_car {
-                        parking_spot_status.trailer_tires_in += 1;
-                    }
-                }
+    for (_, mut parking_spot) in &mut parking_spots {
+        parking_spot.contained_corners.clear();
+    }
+    for (entity1, entity2, intersecting) in rapier_context.intersection_pairs() {
+        if !intersecting {
+            continue;
+        }
+        for (spot_entity, corner_entity) in [(entity1, entity2), (entity2, entity1)] {
+            if !corners.contains(corner_entity) {
+                continue;
+            }
+            if let Ok((_, mut parking_spot)) = parking_spots.get_mut(spot_entity) {
+                parking_spot.contained_corners.insert(corner_entity);
             }
-            CollisionEvent::Stopped(_, left_entity, _) => {
-                if let Ok(tire_collider) = tire_colliders.get(*left_entity) {
-                    if !tire_collider.is_car {
-                        parking_spot_status.trailer_tires_in -= 1;
-                    }
-                }
+        }
+    }
+
+    let mut corner_counts: HashMap<Entity, usize> = HashMap::new();
+    for parent in &corners {
+        *corner_counts.entry(parent.get()).or_insert(0) += 1;
+    }
+    for (spot_transform, mut parking_spot) in &mut parking_spots {
+        let mut corners_inside: HashMap<Entity, usize> = HashMap::new();
+        for corner in &parking_spot.contained_corners {
+            if let Ok(parent) = corners.get(*corner) {
+                *corners_inside.entry(parent.get()).or_insert(0) += 1;
+            }
+        }
+
+        let spot_axis = spot_transform.right();
+        let mut car_in = false;
+        let mut trailer_in = false;
+        for (vehicle, count) in corners_inside {
+            let Ok((vehicle_transform, car, trailer)) = vehicles.get(vehicle) else {
+                continue;
+            };
+            let aligned =
+                axis_angle(vehicle_transform.right(), spot_axis) <= parking_spot.heading_tolerance;
+            if count == corner_counts[&vehicle] && aligned {
+                car_in |= car.is_some();
+                trailer_in |= trailer.is_some();
+            }
+        }
+        parking_spot.car_in = car_in;
+        parking_spot.trailer_in = trailer_in;
+    }
+}
+
+/// Moves on to the next objective once the current spot is filled and the vehicles it needs have
+/// stopped, so the parking score is taken where the rig comes to rest rather than as it rolls in.
+pub fn advance_objectives(
+    mut objectives: ResMut<Objectives>,
+    parking_spots: Query<(&ParkingSpot, &Name)>,
+    cars: Query<&Velocity, With<Car>>,
+    trailers: Query<&Velocity, With<Trailer>>,
+    mut objective_completed_events: EventWriter<ObjectiveCompleted>,
+) {
+    let Some(current_spot) = objectives.current_spot() else {
+        return;
+    };
+    if let Ok((parking_spot, name)) = parking_spots.get(current_spot) {
+        let stopped = |velocity: &Velocity| velocity.linvel.length() < STOPPED_SPEED;
+        let vehicles_stopped = match parking_spot.required_vehicle {
+            RequiredVehicle::Car => cars.iter().all(stopped),
+            RequiredVehicle::Trailer => trailers.iter().all(stopped),
+            RequiredVehicle::Both => cars.iter().chain(trailers.iter()).all(stopped),
+        };
+        if parking_spot.is_filled() && vehicles_stopped {
+            info!("parked in {}", name);
+            objective_completed_events.send(ObjectiveCompleted { spot: current_spot });
+            objectives.current += 1;
+            if objectives.is_complete() {
+                info!("all objectives complete");
             }
         }
     }
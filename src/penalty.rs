@@ -0,0 +1,182 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car::{Drivable, ResetRig},
+    game_state::{FailReason, GameState},
+    hitch::Jackknifed,
+};
+
+pub struct PenaltyPlugin;
+
+impl Plugin for PenaltyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PenaltyRules>()
+            .init_resource::<Penalties>()
+            .add_systems(
+                Update,
+                (
+                    set_contact_force_thresholds,
+                    reset_penalties,
+                    (count_hits, count_jackknifes).run_if(in_state(GameState::Playing)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// How a level punishes the player for running into things and jackknifing the rig.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PenaltyRules {
+    /// Smallest impulse, in newton seconds, that counts as a hit. Anything softer is just
+    /// scraping past.
+    pub impulse_threshold: f32,
+    /// Points taken off the level score for each hit.
+    pub points_per_hit: u32,
+    /// The attempt fails as soon as the player has hit something more than this many times.
+    pub max_hits: Option<u32>,
+    /// Points taken off the level score each time a hitch goes past its jackknife angle.
+    pub points_per_jackknife: u32,
+    /// Whether jackknifing the rig ends the attempt straight away.
+    pub fail_on_jackknife: bool,
+}
+
+impl Default for PenaltyRules {
+    fn default() -> Self {
+        Self {
+            impulse_threshold: 20.0,
+            points_per_hit: 5,
+            max_hits: None,
+            points_per_jackknife: 10,
+            fail_on_jackknife: false,
+        }
+    }
+}
+
+/// Everything the player has run into, or jackknifed, during the current attempt.
+#[derive(Resource, Default)]
+pub struct Penalties {
+    pub hits: u32,
+    /// Every collider that has been hit at least once, such as walls and boxes knocked over.
+    pub touched: HashSet<Entity>,
+    pub jackknifes: u32,
+    /// Points to take off the level score.
+    pub points: u32,
+}
+
+fn reset_penalties(mut reset_events: EventReader<ResetRig>, mut penalties: ResMut<Penalties>) {
+    if reset_events.iter().count() > 0 {
+        *penalties = Penalties::default();
+    }
+}
+
+/// Has rapier only report contacts that could be hard enough to count as a hit, instead of every
+/// touch on every vehicle every step.
+fn set_contact_force_thresholds(
+    rules: Res<PenaltyRules>,
+    rapier_configuration: Res<RapierConfiguration>,
+    added_drivables: Query<(), Added<Drivable>>,
+    mut thresholds: Query<&mut ContactForceEventThreshold, With<Drivable>>,
+) {
+    if !(rules.is_changed() || rapier_configuration.is_changed() || !added_drivables.is_empty()) {
+        return;
+    }
+    // the longer the step, the smaller the force that adds up to the threshold impulse
+    let longest_step = match rapier_configuration.timestep_mode {
+        TimestepMode::Fixed { dt, .. } | TimestepMode::Interpolated { dt, .. } => dt,
+        TimestepMode::Variable { max_dt, .. } => max_dt,
+    };
+    let force_threshold = rules.impulse_threshold / longest_step.max(f32::EPSILON);
+    for mut threshold in &mut thresholds {
+        threshold.0 = force_threshold;
+    }
+}
+
+/// Counts a hit every time a vehicle starts pushing against something hard enough, so leaning
+/// on a wall only counts once.
+#[allow(clippy::too_many_arguments)]
+fn count_hits(
+    mut contact_force_events: EventReader<ContactForceEvent>,
+    drivables: Query<(), With<Drivable>>,
+    names: Query<&Name>,
+    time: Res<Time>,
+    rules: Res<PenaltyRules>,
+    mut penalties: ResMut<Penalties>,
+    mut fail_reason: ResMut<FailReason>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut pushing: Local<HashSet<(Entity, Entity)>>,
+) {
+    let mut still_pushing = HashSet::new();
+    for event in contact_force_events.iter() {
+        // rapier reports the average force over the step it just took
+        let impulse = event.total_force_magnitude * time.delta_seconds();
+        if impulse < rules.impulse_threshold {
+            continue;
+        }
+        let (vehicle, other) = match (
+            drivables.contains(event.collider1),
+            drivables.contains(event.collider2),
+        ) {
+            (true, false) => (event.collider1, event.collider2),
+            (false, true) => (event.collider2, event.collider1),
+            // vehicles in the rig bumping into each other are punished by jackknifing instead
+            _ => continue,
+        };
+        still_pushing.insert((vehicle, other));
+        if pushing.contains(&(vehicle, other)) {
+            continue;
+        }
+
+        penalties.hits += 1;
+        penalties.points += rules.points_per_hit;
+        penalties.touched.insert(other);
+        info!(
+            "hit {} with an impulse of {:.1}Ns ({} hits)",
+            names.get(other).map_or("something", |name| name.as_str()),
+            impulse,
+            penalties.hits
+        );
+        if rules
+            .max_hits
+            .is_some_and(|max_hits| penalties.hits > max_hits)
+        {
+            fail_reason.0 = format!("Hit something {} times", penalties.hits);
+            next_state.set(GameState::Failed);
+        }
+    }
+    *pushing = still_pushing;
+}
+
+/// Counts every time a hitch in the rig jackknifes. Staying jackknifed only counts once, the
+/// hitch has to straighten out before it can count again.
+fn count_jackknifes(
+    mut jackknifed_events: EventReader<Jackknifed>,
+    names: Query<&Name>,
+    rules: Res<PenaltyRules>,
+    mut penalties: ResMut<Penalties>,
+    mut fail_reason: ResMut<FailReason>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for Jackknifed { front, rear, angle } in jackknifed_events.iter() {
+        let name = |entity: &Entity| {
+            names
+                .get(*entity)
+                .map_or_else(|_| format!("{:?}", entity), |name| name.to_string())
+        };
+        penalties.jackknifes += 1;
+        penalties.points += rules.points_per_jackknife;
+        info!(
+            "{} jackknifed behind {} at {:.0}° ({} jackknifes)",
+            name(rear),
+            name(front),
+            angle.to_degrees(),
+            penalties.jackknifes
+        );
+        if rules.fail_on_jackknife {
+            fail_reason.0 = format!("{} jackknifed", name(rear));
+            next_state.set(GameState::Failed);
+        }
+    }
+}
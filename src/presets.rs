@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car::{Tire, VehicleConfig},
+    storage,
+};
+
+/// Directory, in `storage`, that tuned vehicle presets are saved in.
+const PRESET_DIRECTORY: &str = "presets";
+const PRESET_EXTENSION: &str = ".preset.ron";
+
+/// A snapshot of a vehicle's tuning, including the settings of each of its tires.
+#[derive(Serialize, Deserialize)]
+pub struct VehiclePreset {
+    pub vehicle: VehicleConfig,
+    /// Tire settings keyed by the tire's `Name`.
+    pub tires: Vec<(String, Tire)>,
+}
+
+impl VehiclePreset {
+    pub fn tire(&self, name: &str) -> Option<&Tire> {
+        self.tires
+            .iter()
+            .find(|(tire_name, _)| tire_name == name)
+            .map(|(_, tire)| tire)
+    }
+}
+
+fn preset_key(name: &str) -> String {
+    format!("{}/{}{}", PRESET_DIRECTORY, name, PRESET_EXTENSION)
+}
+
+/// Preset names end up as file names, so only allow characters that are safe on every platform.
+pub fn is_valid_preset_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == ' ')
+}
+
+/// Returns the names of all saved presets, sorted alphabetically.
+pub fn list_presets() -> Vec<String> {
+    let prefix = format!("{}/", PRESET_DIRECTORY);
+    let mut names: Vec<String> = storage::saved_keys(PRESET_DIRECTORY)
+        .iter()
+        .filter_map(|key| {
+            key.strip_prefix(&prefix)?
+                .strip_suffix(PRESET_EXTENSION)
+                .map(String::from)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn save_preset(name: &str, preset: &VehiclePreset) -> Result<(), String> {
+    let serialized = ron::ser::to_string_pretty(preset, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())?;
+    storage::write_saved(&preset_key(name), &serialized)
+}
+
+pub fn load_preset(name: &str) -> Result<VehiclePreset, String> {
+    let contents =
+        storage::read_saved(&preset_key(name)).ok_or_else(|| "no such preset".to_string())?;
+    let preset: VehiclePreset = ron::from_str(&contents).map_err(|e| e.to_string())?;
+    preset.vehicle.validate()?;
+    Ok(preset)
+}
@@ -0,0 +1,98 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game_state::{GameState, LevelTimer},
+    level::{CurrentLevel, Levels},
+    penalty::Penalties,
+    score::ParkingScores,
+    storage,
+};
+
+/// Where progress is saved, see `storage`.
+const PROGRESS_KEY: &str = "progress.ron";
+
+pub struct ProgressPlugin;
+
+impl Plugin for ProgressPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_progress())
+            .add_systems(OnEnter(GameState::LevelComplete), record_level_result);
+    }
+}
+
+/// The player's best result on a level they've completed.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct LevelRecord {
+    pub best_score: u32,
+    /// Fastest completion, in seconds. Not necessarily from the same attempt as the best score.
+    pub best_time: f32,
+}
+
+/// Every level the player has completed, keyed by the level's file name.
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct Progress {
+    pub levels: HashMap<String, LevelRecord>,
+}
+
+impl Progress {
+    pub fn record(&self, file_name: &str) -> Option<&LevelRecord> {
+        self.levels.get(file_name)
+    }
+
+    /// The first level is always open, and completing a level opens the next one.
+    pub fn is_unlocked(&self, index: usize, levels: &Levels) -> bool {
+        index == 0
+            || levels
+                .0
+                .get(index - 1)
+                .is_some_and(|previous| self.levels.contains_key(&previous.file_name))
+    }
+}
+
+fn load_progress() -> Progress {
+    let Some(contents) = storage::read_saved(PROGRESS_KEY) else {
+        return Progress::default();
+    };
+    ron::from_str(&contents).unwrap_or_else(|e| {
+        warn!("couldn't read saved progress, starting over: {}", e);
+        Progress::default()
+    })
+}
+
+fn save_progress(progress: &Progress) {
+    let result = ron::ser::to_string_pretty(progress, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())
+        .and_then(|contents| storage::write_saved(PROGRESS_KEY, &contents));
+    if let Err(e) = result {
+        warn!("couldn't save progress: {}", e);
+    }
+}
+
+fn record_level_result(
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+    parking_scores: Res<ParkingScores>,
+    penalties: Res<Penalties>,
+    level_timer: Res<LevelTimer>,
+    mut progress: ResMut<Progress>,
+) {
+    let Some(level_info) = levels.0.get(current_level.index) else {
+        return;
+    };
+    let score = parking_scores
+        .total()
+        .unwrap_or(0)
+        .saturating_sub(penalties.points);
+    let time = level_timer.elapsed;
+    let record = progress
+        .levels
+        .entry(level_info.file_name.clone())
+        .or_insert(LevelRecord {
+            best_score: score,
+            best_time: time,
+        });
+    record.best_score = record.best_score.max(score);
+    record.best_time = record.best_time.min(time);
+    save_progress(&progress);
+}
@@ -0,0 +1,280 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    car::{Car, Trailer},
+    parking_spot::{
+        advance_objectives, axis_angle, ObjectiveCompleted, Objectives, ParkingSpot,
+        RequiredVehicle,
+    },
+};
+
+/// Speed, in m/s, at or above which a vehicle gets no points for stopping.
+const MAX_SCORED_SPEED: f32 = 2.0;
+const ANGLE_WEIGHT: f32 = 40.0;
+const CENTERING_WEIGHT: f32 = 40.0;
+const SPEED_WEIGHT: f32 = 20.0;
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParkingScores>().add_systems(
+            Update,
+            (
+                score_current_parking_spot.before(advance_objectives),
+                record_parking_scores.after(advance_objectives),
+            ),
+        );
+    }
+}
+
+/// How well a vehicle is parked in a spot.
+#[derive(Clone, Copy, Debug)]
+pub struct ParkingScore {
+    /// Radians between the vehicle's long axis and the spot's.
+    pub angle_error: f32,
+    /// Meters between the vehicle's center and the spot's, along the ground.
+    pub centering_offset: f32,
+    /// Meters per second the vehicles were going as they pulled into the spot, or are going now
+    /// if they aren't in it yet.
+    pub speed: f32,
+    /// 0 to 100.
+    pub score: u32,
+    /// 0 to 3.
+    pub stars: u32,
+}
+
+impl ParkingScore {
+    /// Combines how far off a vehicle is into a score, given each measurement as a fraction of
+    /// the most the spot allows.
+    fn new(
+        angle_error: f32,
+        centering_offset: f32,
+        speed: f32,
+        angle_fraction: f32,
+        offset_fraction: f32,
+    ) -> Self {
+        let angle_score = 1.0 - angle_fraction.min(1.0);
+        let centering_score = 1.0 - offset_fraction.min(1.0);
+        let speed_score = 1.0 - (speed / MAX_SCORED_SPEED).min(1.0);
+        let score = (ANGLE_WEIGHT * angle_score
+            + CENTERING_WEIGHT * centering_score
+            + SPEED_WEIGHT * speed_score)
+            .round() as u32;
+        let stars = match score {
+            90.. => 3,
+            70..=89 => 2,
+            40..=69 => 1,
+            _ => 0,
+        };
+        Self {
+            angle_error,
+            centering_offset,
+            speed,
+            score,
+            stars,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ParkingScores {
+    /// The score the player would get for the current objective if they stopped right now.
+    pub current: Option<ParkingScore>,
+    /// Final scores of finished objectives, in the order they were finished.
+    pub completed: Vec<(Entity, ParkingScore)>,
+    /// The spot the required vehicles are in and how fast they were going as they filled it.
+    /// Objectives only finish once the rig has stopped, so this is what the speed is scored on.
+    entry_speed: Option<(Entity, f32)>,
+}
+
+impl ParkingScores {
+    /// Average score of all finished objectives.
+    pub fn total(&self) -> Option<u32> {
+        if self.completed.is_empty() {
+            return None;
+        }
+        let sum: u32 = self.completed.iter().map(|(_, score)| score.score).sum();
+        Some(sum / self.completed.len() as u32)
+    }
+}
+
+fn score_vehicle(
+    vehicle_transform: &GlobalTransform,
+    speed: f32,
+    spot_transform: &GlobalTransform,
+    parking_spot: &ParkingSpot,
+) -> ParkingScore {
+    let spot_size = spot_transform.compute_transform().scale;
+    let offset = vehicle_transform.translation() - spot_transform.translation();
+    let along = offset.dot(spot_transform.right());
+    let across = offset.dot(spot_transform.back());
+    let angle_error = axis_angle(vehicle_transform.right(), spot_transform.right());
+    // 1 at the edge of the spot in either direction
+    let offset_fraction = Vec2::new(
+        along / (spot_size.x / 2.0).max(f32::EPSILON),
+        across / (spot_size.z / 2.0).max(f32::EPSILON),
+    )
+    .length();
+    ParkingScore::new(
+        angle_error,
+        Vec2::new(along, across).length(),
+        speed,
+        angle_error / parking_spot.heading_tolerance.clamp(f32::EPSILON, PI / 2.0),
+        offset_fraction,
+    )
+}
+
+fn score_current_parking_spot(
+    objectives: Res<Objectives>,
+    parking_spots: Query<(&GlobalTransform, &ParkingSpot)>,
+    cars: Query<(&GlobalTransform, &Velocity), With<Car>>,
+    trailers: Query<(&GlobalTransform, &Velocity), With<Trailer>>,
+    mut parking_scores: ResMut<ParkingScores>,
+) {
+    parking_scores.current = None;
+    let Some((spot, (spot_transform, parking_spot))) = objectives
+        .current_spot()
+        .and_then(|spot| Some((spot, parking_spots.get(spot).ok()?)))
+    else {
+        parking_scores.entry_speed = None;
+        return;
+    };
+
+    let entry_speed = if parking_spot.is_filled() {
+        let speed = |(_, velocity): (&GlobalTransform, &Velocity)| velocity.linvel.length();
+        let fastest = match parking_spot.required_vehicle {
+            RequiredVehicle::Car => cars.iter().map(speed).fold(0.0, f32::max),
+            RequiredVehicle::Trailer => trailers.iter().map(speed).fold(0.0, f32::max),
+            RequiredVehicle::Both => cars
+                .iter()
+                .chain(trailers.iter())
+                .map(speed)
+                .fold(0.0, f32::max),
+        };
+        let speed = match parking_scores.entry_speed {
+            Some((entered, speed)) if entered == spot => speed,
+            _ => fastest,
+        };
+        Some((spot, speed))
+    } else {
+        None
+    };
+    parking_scores.entry_speed = entry_speed;
+
+    let best_score = |vehicles: &mut dyn Iterator<Item = (&GlobalTransform, &Velocity)>| {
+        vehicles
+            .map(|(transform, velocity)| {
+                let speed = entry_speed.map_or(velocity.linvel.length(), |(_, speed)| speed);
+                score_vehicle(transform, speed, spot_transform, parking_spot)
+            })
+            .max_by_key(|score| score.score)
+    };
+    let car_score = best_score(&mut cars.iter());
+    let trailer_score = best_score(&mut trailers.iter());
+    parking_scores.current = match parking_spot.required_vehicle {
+        RequiredVehicle::Car => car_score,
+        RequiredVehicle::Trailer => trailer_score,
+        RequiredVehicle::Both => car_score
+            .zip(trailer_score)
+            .map(|(car_score, trailer_score)| {
+                // the rig is only as well parked as its worst vehicle
+                if car_score.score < trailer_score.score {
+                    car_score
+                } else {
+                    trailer_score
+                }
+            }),
+    };
+}
+
+fn record_parking_scores(
+    mut objective_completed_events: EventReader<ObjectiveCompleted>,
+    mut parking_scores: ResMut<ParkingScores>,
+) {
+    for ObjectiveCompleted { spot } in objective_completed_events.iter() {
+        if let Some(score) = parking_scores.current {
+            info!(
+                "scored {} ({} stars): {:.1}° off, {:.2}m from center, {:.2}m/s",
+                score.score,
+                score.stars,
+                score.angle_error.to_degrees(),
+                score.centering_offset,
+                score.speed
+            );
+            parking_scores.completed.push((*spot, score));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perfect_parking_scores_full_marks() {
+        let score = ParkingScore::new(0.0, 0.0, 0.0, 0.0, 0.0);
+        assert_eq!(score.score, 100);
+        assert_eq!(score.stars, 3);
+    }
+
+    #[test]
+    fn parking_at_the_limits_scores_nothing() {
+        let score = ParkingScore::new(0.3, 2.0, MAX_SCORED_SPEED, 1.0, 1.0);
+        assert_eq!(score.score, 0);
+        assert_eq!(score.stars, 0);
+    }
+
+    #[test]
+    fn measurements_past_the_limits_do_not_go_negative() {
+        let score = ParkingScore::new(1.0, 5.0, 10.0 * MAX_SCORED_SPEED, 3.0, 3.0);
+        assert_eq!(score.score, 0);
+    }
+
+    #[test]
+    fn each_measurement_scores_on_its_own_weight() {
+        let angle_only = ParkingScore::new(0.0, 2.0, MAX_SCORED_SPEED, 0.0, 1.0);
+        let centering_only = ParkingScore::new(0.3, 0.0, MAX_SCORED_SPEED, 1.0, 0.0);
+        let speed_only = ParkingScore::new(0.3, 2.0, 0.0, 1.0, 1.0);
+        assert_eq!(angle_only.score, ANGLE_WEIGHT as u32);
+        assert_eq!(centering_only.score, CENTERING_WEIGHT as u32);
+        assert_eq!(speed_only.score, SPEED_WEIGHT as u32);
+    }
+
+    #[test]
+    fn half_way_off_scores_half() {
+        let score = ParkingScore::new(0.15, 1.0, MAX_SCORED_SPEED / 2.0, 0.5, 0.5);
+        assert_eq!(score.score, 50);
+        assert_eq!(score.stars, 1);
+    }
+
+    #[test]
+    fn stars_follow_the_score() {
+        // only centering varies, so the score is 60 + 40 * (1 - offset)
+        let stars =
+            |offset_fraction: f32| ParkingScore::new(0.0, 0.0, 0.0, 0.0, offset_fraction).stars;
+        assert_eq!(stars(0.25), 3);
+        assert_eq!(stars(0.5), 2);
+        assert_eq!(stars(1.0), 1);
+    }
+
+    #[test]
+    fn total_averages_completed_objectives() {
+        let mut scores = ParkingScores::default();
+        assert_eq!(scores.total(), None);
+        scores.completed = vec![
+            (
+                Entity::from_raw(0),
+                ParkingScore::new(0.0, 0.0, 0.0, 0.0, 0.0),
+            ),
+            (
+                Entity::from_raw(1),
+                ParkingScore::new(0.0, 0.0, 0.0, 0.0, 0.5),
+            ),
+        ];
+        assert_eq!(scores.total(), Some(90));
+    }
+}
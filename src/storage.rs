@@ -0,0 +1,60 @@
+//! Small saved files, such as progress and settings: files in the working directory on native
+//! builds, and `localStorage` keys on the web.
+//!
+//! Keys may contain a directory, like `presets/fast.preset.ron`, to keep related files together.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_saved(key: &str) -> Option<String> {
+    std::fs::read_to_string(key).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_saved(key: &str, contents: &str) -> Result<(), String> {
+    if let Some(directory) = std::path::Path::new(key).parent() {
+        std::fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(key, contents).map_err(|e| e.to_string())
+}
+
+/// Every saved key in `directory`, e.g. `presets/fast.preset.ron` for `presets`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn saved_keys(directory: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| Some(format!("{}/{}", directory, entry.file_name().to_str()?)))
+        .collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_saved(key: &str) -> Option<String> {
+    local_storage()?.get_item(key).ok()?
+}
+
+/// Every saved key in `directory`, e.g. `presets/fast.preset.ron` for `presets`.
+#[cfg(target_arch = "wasm32")]
+pub fn saved_keys(directory: &str) -> Vec<String> {
+    let Some(storage) = local_storage() else {
+        return Vec::new();
+    };
+    let prefix = format!("{}/", directory);
+    (0..storage.length().unwrap_or(0))
+        .filter_map(|i| storage.key(i).ok()?)
+        .filter(|key| key.starts_with(&prefix))
+        .collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_saved(key: &str, contents: &str) -> Result<(), String> {
+    local_storage()
+        .ok_or("local storage isn't available")?
+        .set_item(key, contents)
+        .map_err(|e| format!("{:?}", e))
+}
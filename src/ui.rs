@@ -1,10 +1,29 @@
 use std::f32::consts::PI;
 
-use bevy::{input::common_conditions::input_toggle_active, prelude::*};
-use bevy_inspector_egui::{bevy_egui::EguiContexts, egui::Slider};
+use bevy::{input::common_conditions::input_toggle_active, prelude::*, utils::HashMap};
+use bevy_inspector_egui::{
+    bevy_egui::EguiContexts,
+    egui::{
+        self,
+        plot::{Line, Plot, PlotPoints, Points},
+        ComboBox, DragValue, Slider,
+    },
+};
 use bevy_rapier3d::prelude::*;
 
-use crate::car::{Tire, VehicleConfig};
+use crate::{
+    car::{Car, ResetRig, Tire, VehicleConfig},
+    controls::{self, Action, ControlBindings},
+    drivetrain::{PowerCurve, Transmission},
+    game_state::{Attempts, FailReason, GameState, LevelTimer},
+    hitch::{ArticulationAngle, ArticulationAngles},
+    level::{ChangeLevel, CurrentLevel, LevelDefinition, Levels},
+    parking_spot::{Objectives, ParkingSpot, RequiredVehicle},
+    penalty::Penalties,
+    presets::{self, VehiclePreset},
+    progress::Progress,
+    score::ParkingScores,
+};
 
 pub struct UIPlugin;
 
@@ -12,25 +31,64 @@ impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            config_ui_system.run_if(input_toggle_active(true, KeyCode::Escape)),
+            (
+                config_ui_system.run_if(input_toggle_active(true, Action::ToggleSettings)),
+                articulation_gauge_ui_system,
+                objectives_ui_system,
+                main_menu_ui_system.run_if(in_state(GameState::MainMenu)),
+                level_select_ui_system.run_if(in_state(GameState::LevelSelect)),
+                controls_ui_system.run_if(in_state(GameState::Controls)),
+                pause_menu_ui_system.run_if(in_state(GameState::Paused)),
+                level_complete_ui_system.run_if(in_state(GameState::LevelComplete)),
+                failed_ui_system.run_if(in_state(GameState::Failed)),
+            ),
         );
     }
 }
 
+/// State of the preset controls in one vehicle's settings window.
+#[derive(Default)]
+struct PresetUiState {
+    save_name: String,
+    selected: Option<String>,
+    status: String,
+}
+
 fn config_ui_system(
     mut contexts: EguiContexts,
     mut vehicle_configs: Query<(Entity, &mut VehicleConfig, &Name, &Children)>,
     mut tires: Query<(&mut Tire, &Name)>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut preset_ui_states: Local<HashMap<Entity, PresetUiState>>,
+    mut preset_names: Local<Option<Vec<String>>>,
 ) {
+    let preset_names = preset_names.get_or_insert_with(presets::list_presets);
     for (vehicle_entity, mut vehicle_config, vehicle_name, vehicle_children) in &mut vehicle_configs
     {
         bevy_inspector_egui::egui::Window::new(format!("{} Settings", vehicle_name)).show(
             contexts.ctx_mut(),
             |ui| {
                 ui.add(Slider::new(&mut vehicle_config.max_speed, 5.0..=200.0).text("max speed"));
-                ui.add(Slider::new(&mut vehicle_config.max_force, 50.0..=1000.0).text("max force"));
+                let drivetrain = &mut vehicle_config.drivetrain;
+                ui.add(Slider::new(&mut drivetrain.max_torque, 0.0..=50.0).text("engine torque"));
+                ui.collapsing("power curve", |ui| {
+                    edit_power_curve(ui, vehicle_name, &mut drivetrain.power_curve);
+                });
+                ui.add(Slider::new(&mut drivetrain.final_drive, 0.5..=10.0).text("final drive"));
+                ui.horizontal(|ui| {
+                    ui.label("gear ratios");
+                    for ratio in drivetrain.gear_ratios.iter_mut() {
+                        ui.add(DragValue::new(ratio).speed(0.05).clamp_range(0.1..=10.0));
+                    }
+                    ui.label("reverse");
+                    ui.add(
+                        DragValue::new(&mut drivetrain.reverse_ratio)
+                            .speed(0.05)
+                            .clamp_range(0.1..=10.0),
+                    );
+                });
+                ui.checkbox(&mut drivetrain.automatic, "automatic gearbox");
                 ui.add(
                     Slider::new(&mut vehicle_config.spring_offset, 0.0..=10.0)
                         .text("suspension height"),
@@ -47,26 +105,46 @@ fn config_ui_system(
                 let length_slider =
                     ui.add(Slider::new(&mut vehicle_config.length, 0.1..=10.0).text("length"));
                 if height_slider.changed() || width_slider.changed() || length_slider.changed() {
-                    commands.entity(vehicle_entity).insert((
-                        Collider::cuboid(
-                            vehicle_config.length,
-                            vehicle_config.height,
-                            vehicle_config.width,
-                        ),
-                        meshes.add(Mesh::from(shape::Box {
-                            min_x: -vehicle_config.length,
-                            max_x: vehicle_config.length,
-                            min_y: -vehicle_config.height,
-                            max_y: vehicle_config.height,
-                            min_z: -vehicle_config.width,
-                            max_z: vehicle_config.width,
-                        })),
-                    ));
+                    resize_vehicle(&mut commands, &mut meshes, vehicle_entity, &vehicle_config);
                 };
                 ui.add(
                     Slider::new(&mut vehicle_config.turn_radius, 0.0..=(PI / 4.0))
                         .text("turn radius"),
                 );
+                ui.add(
+                    Slider::new(&mut vehicle_config.steering.rate, 0.1..=5.0).text("steering rate"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.steering.centering_rate, 0.1..=5.0)
+                        .text("self-centering rate"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.steering.high_speed_lock, 0.0..=1.0)
+                        .text("steering lock at max speed"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.steering.ackermann, 0.0..=1.0)
+                        .text("ackermann"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.brakes.max_torque, 0.0..=500.0)
+                        .text("brake torque"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.brakes.front_bias, 0.0..=1.0)
+                        .text("front brake bias"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.brakes.handbrake_torque, 0.0..=500.0)
+                        .text("handbrake torque"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.resistance.rolling, 0.0..=1.0)
+                        .text("rolling resistance"),
+                );
+                ui.add(
+                    Slider::new(&mut vehicle_config.resistance.drag, 0.0..=0.2).text("air drag"),
+                );
 
                 for child in vehicle_children {
                     let tire = tires.get_mut(*child);
@@ -75,12 +153,655 @@ fn config_ui_system(
                             ui.horizontal(|ui| {
                                 ui.checkbox(&mut tire.connected_to_engine, "spins");
                                 ui.checkbox(&mut tire.turns, "turns");
-                                ui.add(Slider::new(&mut tire.grip, 0.0..=1.0).text("grip"));
+                                ui.add(Slider::new(&mut tire.grip, 0.0..=2.0).text("grip"));
                             });
+                            ui.add(Slider::new(&mut tire.stiffness, 1.0..=20.0).text("stiffness"));
+                            ui.add(Slider::new(&mut tire.shape, 1.0..=2.0).text("shape"));
+                            ui.add(Slider::new(&mut tire.curvature, -1.0..=1.0).text("curvature"));
+                        });
+                    }
+                }
+
+                ui.separator();
+                let preset_ui = preset_ui_states.entry(vehicle_entity).or_default();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut preset_ui.save_name);
+                    if ui.button("Save preset").clicked() {
+                        let name = preset_ui.save_name.trim().to_string();
+                        if !presets::is_valid_preset_name(&name) {
+                            preset_ui.status =
+                                "preset names may only use letters, numbers, spaces, - and _"
+                                    .to_string();
+                            return;
+                        }
+                        let preset = VehiclePreset {
+                            vehicle: vehicle_config.clone(),
+                            tires: vehicle_children
+                                .iter()
+                                .filter_map(|child| tires.get(*child).ok())
+                                .map(|(tire, tire_name)| (tire_name.to_string(), tire.clone()))
+                                .collect(),
+                        };
+                        preset_ui.status = match presets::save_preset(&name, &preset) {
+                            Ok(()) => {
+                                *preset_names = presets::list_presets();
+                                preset_ui.selected = Some(name.clone());
+                                format!("saved {}", name)
+                            }
+                            Err(e) => format!("could not save {}: {}", name, e),
+                        };
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ComboBox::from_id_source(("preset", vehicle_entity))
+                        .selected_text(preset_ui.selected.as_deref().unwrap_or("choose preset"))
+                        .show_ui(ui, |ui| {
+                            for preset_name in preset_names.iter() {
+                                ui.selectable_value(
+                                    &mut preset_ui.selected,
+                                    Some(preset_name.clone()),
+                                    preset_name,
+                                );
+                            }
                         });
+                    let Some(name) = preset_ui.selected.clone() else {
+                        return;
+                    };
+                    if ui.button("Load preset").clicked() {
+                        preset_ui.status = match presets::load_preset(&name) {
+                            Ok(preset) => {
+                                vehicle_config.apply_tuning(&preset.vehicle);
+                                resize_vehicle(
+                                    &mut commands,
+                                    &mut meshes,
+                                    vehicle_entity,
+                                    &vehicle_config,
+                                );
+                                for child in vehicle_children {
+                                    if let Ok((mut tire, tire_name)) = tires.get_mut(*child) {
+                                        if let Some(preset_tire) = preset.tire(tire_name) {
+                                            tire.connected_to_engine =
+                                                preset_tire.connected_to_engine;
+                                            tire.turns = preset_tire.turns;
+                                            tire.grip = preset_tire.grip;
+                                            tire.stiffness = preset_tire.stiffness;
+                                            tire.shape = preset_tire.shape;
+                                            tire.curvature = preset_tire.curvature;
+                                        }
+                                    }
+                                }
+                                format!("loaded {}", name)
+                            }
+                            Err(e) => format!("could not load {}: {}", name, e),
+                        };
                     }
+                });
+                if !preset_ui.status.is_empty() {
+                    ui.label(&preset_ui.status);
                 }
             },
         );
     }
 }
+
+fn resize_vehicle(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    vehicle_entity: Entity,
+    vehicle_config: &VehicleConfig,
+) {
+    commands.entity(vehicle_entity).insert((
+        Collider::cuboid(
+            vehicle_config.length,
+            vehicle_config.height,
+            vehicle_config.width,
+        ),
+        meshes.add(Mesh::from(shape::Box {
+            min_x: -vehicle_config.length,
+            max_x: vehicle_config.length,
+            min_y: -vehicle_config.height,
+            max_y: vehicle_config.height,
+            min_z: -vehicle_config.width,
+            max_z: vehicle_config.width,
+        })),
+    ));
+}
+
+fn stars(count: u32) -> String {
+    (0..3).map(|i| if i < count { '★' } else { '☆' }).collect()
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds.max(0.0);
+    format!("{}:{:04.1}", (seconds / 60.0) as u32, seconds % 60.0)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn objectives_ui_system(
+    mut contexts: EguiContexts,
+    objectives: Res<Objectives>,
+    parking_spots: Query<(&ParkingSpot, &Name)>,
+    parking_scores: Res<ParkingScores>,
+    level_timer: Res<LevelTimer>,
+    attempts: Res<Attempts>,
+    penalties: Res<Penalties>,
+    car: Query<(&Transmission, &VehicleConfig), With<Car>>,
+) {
+    if objectives.steps.is_empty() {
+        return;
+    }
+    egui::Window::new("Objectives")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10.0, 10.0))
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("Time: {}", format_time(level_timer.elapsed)));
+                if let Some(remaining) = level_timer.remaining() {
+                    ui.label(format!("({} left)", format_time(remaining)));
+                }
+                ui.label(format!("Attempt {}", attempts.0));
+                if let Ok((transmission, config)) = car.get_single() {
+                    ui.label(format!(
+                        "Gear: {} ({:.0} rpm)",
+                        transmission.gear_label(&config.drivetrain),
+                        transmission.rpm
+                    ));
+                }
+            });
+            if penalties.points > 0 {
+                ui.label(format!(
+                    "Hits: {}, jackknifes: {} (-{} points)",
+                    penalties.hits, penalties.jackknifes, penalties.points
+                ));
+            }
+            ui.separator();
+            for (i, spot_entity) in objectives.steps.iter().enumerate() {
+                let Ok((parking_spot, name)) = parking_spots.get(*spot_entity) else {
+                    continue;
+                };
+                let what = match parking_spot.required_vehicle {
+                    RequiredVehicle::Car => "the car",
+                    RequiredVehicle::Trailer => "the trailer",
+                    RequiredVehicle::Both => "the car and trailer",
+                };
+                let text = format!("{}. Park {} in {}", i + 1, what, name);
+                if i < objectives.current {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(text).strikethrough().weak());
+                        if let Some((_, score)) = parking_scores
+                            .completed
+                            .iter()
+                            .find(|(spot, _)| spot == spot_entity)
+                        {
+                            ui.label(format!("{} {}", stars(score.stars), score.score));
+                        }
+                    });
+                } else if i == objectives.current {
+                    ui.label(egui::RichText::new(text).strong());
+                    if let Some(score) = parking_scores.current {
+                        ui.label(format!(
+                            "{:.0}° off, {:.1}m from center, {:.1}m/s: {} {}",
+                            score.angle_error.to_degrees(),
+                            score.centering_offset,
+                            score.speed,
+                            stars(score.stars),
+                            score.score
+                        ));
+                    }
+                } else {
+                    ui.label(text);
+                }
+            }
+            if objectives.is_complete() {
+                ui.label(egui::RichText::new("All done!").strong());
+                if let Some(total) = parking_scores.total() {
+                    ui.label(format!("Score: {}", total.saturating_sub(penalties.points)));
+                }
+            }
+        });
+}
+
+/// A window fixed in the middle of the screen, for menus that stop the game.
+fn menu_window(title: &str) -> egui::Window<'_> {
+    egui::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+}
+
+/// Starts a fresh attempt at the level.
+fn restart(reset_events: &mut EventWriter<ResetRig>, next_state: &mut NextState<GameState>) {
+    reset_events.send(ResetRig);
+    next_state.set(GameState::Playing);
+}
+
+/// Switches to another level and starts the first attempt at it.
+fn play_level(
+    index: usize,
+    change_level_events: &mut EventWriter<ChangeLevel>,
+    reset_events: &mut EventWriter<ResetRig>,
+    next_state: &mut NextState<GameState>,
+) {
+    change_level_events.send(ChangeLevel { index });
+    restart(reset_events, next_state);
+}
+
+fn main_menu_ui_system(
+    mut contexts: EguiContexts,
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+    level_assets: Res<Assets<LevelDefinition>>,
+    mut reset_events: EventWriter<ResetRig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    menu_window("Put It In Reverse").show(contexts.ctx_mut(), |ui| {
+        ui.label("Back the trailer into each parking spot, then come to a stop.");
+        if current_level.index < levels.0.len() {
+            ui.label(format!(
+                "Level: {}",
+                levels.display_name(current_level.index, &level_assets)
+            ));
+        }
+        if ui.button("Play").clicked() {
+            restart(&mut reset_events, &mut next_state);
+        }
+        if ui.button("Select level").clicked() {
+            next_state.set(GameState::LevelSelect);
+        }
+        if ui.button("Edit level").clicked() {
+            next_state.set(GameState::Editing);
+        }
+        if ui.button("Controls").clicked() {
+            next_state.set(GameState::Controls);
+        }
+    });
+}
+
+fn level_select_ui_system(
+    mut contexts: EguiContexts,
+    levels: Res<Levels>,
+    level_assets: Res<Assets<LevelDefinition>>,
+    progress: Res<Progress>,
+    mut change_level_events: EventWriter<ChangeLevel>,
+    mut reset_events: EventWriter<ResetRig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    menu_window("Select Level").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("levels").striped(true).show(ui, |ui| {
+            for (index, level_info) in levels.0.iter().enumerate() {
+                ui.label(format!(
+                    "{}. {}",
+                    index + 1,
+                    levels.display_name(index, &level_assets)
+                ));
+                match progress.record(&level_info.file_name) {
+                    Some(record) => {
+                        ui.label(format!("Best: {}", record.best_score));
+                        ui.label(format_time(record.best_time));
+                    }
+                    None => {
+                        ui.label("-");
+                        ui.label("-");
+                    }
+                }
+                let unlocked = progress.is_unlocked(index, &levels);
+                let text = if unlocked { "Play" } else { "Locked" };
+                if ui.add_enabled(unlocked, egui::Button::new(text)).clicked() {
+                    play_level(
+                        index,
+                        &mut change_level_events,
+                        &mut reset_events,
+                        &mut next_state,
+                    );
+                }
+                ui.end_row();
+            }
+        });
+        if ui.button("Back").clicked() {
+            next_state.set(GameState::MainMenu);
+        }
+    });
+}
+
+/// Lists every action's bindings, and lets the player add and remove them. Changes are saved
+/// straight away.
+#[allow(clippy::too_many_arguments)]
+fn controls_ui_system(
+    mut contexts: EguiContexts,
+    mut bindings: ResMut<ControlBindings>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut rebinding: Local<Option<Action>>,
+) {
+    let mut changed = false;
+    if let Some(action) = *rebinding {
+        if let Some(binding) = controls::pressed_binding(&keys, &gamepad_buttons, &axes, &gamepads)
+        {
+            let action_bindings = bindings.0.entry(action).or_default();
+            if !action_bindings.contains(&binding) {
+                action_bindings.push(binding);
+            }
+            *rebinding = None;
+            changed = true;
+        }
+    }
+
+    menu_window("Controls").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("controls").striped(true).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.label());
+                ui.horizontal(|ui| {
+                    let action_bindings = bindings.0.entry(action).or_default();
+                    let mut removed = None;
+                    for (i, binding) in action_bindings.iter().enumerate() {
+                        if ui
+                            .button(binding.to_string())
+                            .on_hover_text("Click to remove")
+                            .clicked()
+                        {
+                            removed = Some(i);
+                        }
+                    }
+                    if let Some(i) = removed {
+                        action_bindings.remove(i);
+                        changed = true;
+                    }
+                });
+                if *rebinding == Some(action) {
+                    if ui.button("Press a key or button...").clicked() {
+                        *rebinding = None;
+                    }
+                } else if ui.button("Add").clicked() {
+                    *rebinding = Some(action);
+                }
+                ui.end_row();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Reset to defaults").clicked() {
+                *bindings = ControlBindings::default();
+                *rebinding = None;
+                changed = true;
+            }
+            if ui.button("Back").clicked() {
+                *rebinding = None;
+                next_state.set(GameState::MainMenu);
+            }
+        });
+    });
+
+    if changed {
+        controls::save_bindings(&bindings);
+    }
+}
+
+fn pause_menu_ui_system(
+    mut contexts: EguiContexts,
+    mut reset_events: EventWriter<ResetRig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    menu_window("Paused").show(contexts.ctx_mut(), |ui| {
+        if ui.button("Resume").clicked() {
+            next_state.set(GameState::Playing);
+        }
+        if ui.button("Restart").clicked() {
+            restart(&mut reset_events, &mut next_state);
+        }
+        if ui.button("Main menu").clicked() {
+            next_state.set(GameState::MainMenu);
+        }
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn level_complete_ui_system(
+    mut contexts: EguiContexts,
+    objectives: Res<Objectives>,
+    parking_spots: Query<&Name, With<ParkingSpot>>,
+    parking_scores: Res<ParkingScores>,
+    level_timer: Res<LevelTimer>,
+    attempts: Res<Attempts>,
+    penalties: Res<Penalties>,
+    levels: Res<Levels>,
+    current_level: Res<CurrentLevel>,
+    mut change_level_events: EventWriter<ChangeLevel>,
+    mut reset_events: EventWriter<ResetRig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    menu_window("Level Complete").show(contexts.ctx_mut(), |ui| {
+        ui.label(format!("Time: {}", format_time(level_timer.elapsed)));
+        ui.label(format!("Attempts: {}", attempts.0));
+        ui.separator();
+        for spot_entity in &objectives.steps {
+            let name = parking_spots
+                .get(*spot_entity)
+                .map_or("?", |name| name.as_str());
+            if let Some((_, score)) = parking_scores
+                .completed
+                .iter()
+                .find(|(spot, _)| spot == spot_entity)
+            {
+                ui.label(format!("{}: {} {}", name, stars(score.stars), score.score));
+            }
+        }
+        if penalties.points > 0 {
+            ui.label(format!(
+                "{} hits, {} objects touched, {} jackknifes: -{}",
+                penalties.hits,
+                penalties.touched.len(),
+                penalties.jackknifes,
+                penalties.points
+            ));
+        }
+        if let Some(total) = parking_scores.total() {
+            ui.label(
+                egui::RichText::new(format!("Score: {}", total.saturating_sub(penalties.points)))
+                    .strong(),
+            );
+        }
+        ui.separator();
+        let next_level = current_level.index + 1;
+        if next_level < levels.0.len() && ui.button("Next level").clicked() {
+            play_level(
+                next_level,
+                &mut change_level_events,
+                &mut reset_events,
+                &mut next_state,
+            );
+        }
+        if ui.button("Play again").clicked() {
+            restart(&mut reset_events, &mut next_state);
+        }
+        if ui.button("Level select").clicked() {
+            next_state.set(GameState::LevelSelect);
+        }
+        if ui.button("Main menu").clicked() {
+            next_state.set(GameState::MainMenu);
+        }
+    });
+}
+
+fn failed_ui_system(
+    mut contexts: EguiContexts,
+    fail_reason: Res<FailReason>,
+    attempts: Res<Attempts>,
+    mut reset_events: EventWriter<ResetRig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    menu_window("Failed").show(contexts.ctx_mut(), |ui| {
+        ui.label(&fail_reason.0);
+        ui.label(format!("Attempts: {}", attempts.0));
+        if ui.button("Try again").clicked() {
+            restart(&mut reset_events, &mut next_state);
+        }
+        if ui.button("Main menu").clicked() {
+            next_state.set(GameState::MainMenu);
+        }
+    });
+}
+
+fn articulation_gauge_ui_system(
+    mut contexts: EguiContexts,
+    articulation_angles: Res<ArticulationAngles>,
+    names: Query<&Name>,
+) {
+    if articulation_angles.0.is_empty() {
+        return;
+    }
+    egui::Window::new("Hitch Angle")
+        .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                for articulation_angle in &articulation_angles.0 {
+                    ui.vertical(|ui| {
+                        if let Ok([front_name, rear_name]) =
+                            names.get_many([articulation_angle.front, articulation_angle.rear])
+                        {
+                            ui.label(format!("{} to {}", front_name, rear_name));
+                        }
+                        draw_articulation_gauge(ui, articulation_angle);
+                    });
+                }
+            });
+        });
+}
+
+/// Plots the power curve, with a row of values under it for each point.
+fn edit_power_curve(ui: &mut egui::Ui, id: &str, power_curve: &mut PowerCurve) {
+    let to_plot = |point: &Vec2| [point.x as f64, point.y as f64];
+    Plot::new(format!("{} power curve", id))
+        .height(120.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .include_x(0.0)
+        .include_x(1.0)
+        .include_y(0.0)
+        .include_y(1.0)
+        .x_axis_formatter(|x, _| format!("{:.0}%", x * 100.0))
+        .show(ui, |plot_ui| {
+            plot_ui.line(Line::new(
+                power_curve
+                    .points
+                    .iter()
+                    .map(to_plot)
+                    .collect::<PlotPoints>(),
+            ));
+            plot_ui.points(
+                Points::new(
+                    power_curve
+                        .points
+                        .iter()
+                        .map(to_plot)
+                        .collect::<PlotPoints>(),
+                )
+                .radius(3.0),
+            );
+        });
+
+    let mut changed = false;
+    let mut removed = None;
+    let can_remove = power_curve.points.len() > 2;
+    egui::Grid::new(format!("{} power curve points", id)).show(ui, |ui| {
+        ui.label("rpm");
+        ui.label("torque");
+        ui.end_row();
+        for (i, point) in power_curve.points.iter_mut().enumerate() {
+            changed |= ui
+                .add(
+                    DragValue::new(&mut point.x)
+                        .speed(0.01)
+                        .clamp_range(0.0..=1.0),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    DragValue::new(&mut point.y)
+                        .speed(0.01)
+                        .clamp_range(0.0..=2.0),
+                )
+                .changed();
+            if ui.add_enabled(can_remove, egui::Button::new("x")).clicked() {
+                removed = Some(i);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(i) = removed {
+        power_curve.points.remove(i);
+    }
+    if ui.button("Add point").clicked() {
+        // halfway along the widest gap, where a new point is most useful
+        let widest = power_curve
+            .points
+            .windows(2)
+            .max_by(|a, b| (a[1].x - a[0].x).total_cmp(&(b[1].x - b[0].x)))
+            .map(|pair| (pair[0] + pair[1]) / 2.0);
+        if let Some(point) = widest {
+            power_curve.points.push(point);
+            changed = true;
+        }
+    }
+    if changed {
+        power_curve.points.sort_by(|a, b| a.x.total_cmp(&b.x));
+    }
+}
+
+/// Draws a half-circle dial with the needle showing which way the trailer points relative to the
+/// vehicle towing it, and the jackknife zones at either end in red.
+fn draw_articulation_gauge(ui: &mut egui::Ui, articulation_angle: &ArticulationAngle) {
+    let gauge_range = PI / 2.0;
+    let radius = 60.0;
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(radius * 2.0 + 10.0, radius + 30.0),
+        egui::Sense::hover(),
+    );
+    let center = response.rect.center_top() + egui::vec2(0.0, radius + 5.0);
+    // 0 is straight up, and anticlockwise trailer angles point the needle to the left.
+    let point_at = |angle: f32, distance: f32| {
+        let angle = -angle.clamp(-gauge_range, gauge_range);
+        center + distance * egui::vec2(angle.sin(), -angle.cos())
+    };
+    let arc = |from: f32, to: f32| -> Vec<egui::Pos2> {
+        (0..=32)
+            .map(|i| point_at(from + (to - from) * i as f32 / 32.0, radius))
+            .collect()
+    };
+
+    let jackknife_angle = articulation_angle.jackknife_angle.min(gauge_range);
+    let safe_stroke = egui::Stroke::new(6.0, egui::Color32::DARK_GREEN);
+    let danger_stroke = egui::Stroke::new(6.0, egui::Color32::DARK_RED);
+    painter.add(egui::Shape::line(
+        arc(-jackknife_angle, jackknife_angle),
+        safe_stroke,
+    ));
+    painter.add(egui::Shape::line(
+        arc(jackknife_angle, gauge_range),
+        danger_stroke,
+    ));
+    painter.add(egui::Shape::line(
+        arc(-gauge_range, -jackknife_angle),
+        danger_stroke,
+    ));
+
+    let needle_color = if articulation_angle.is_jackknifed() {
+        egui::Color32::RED
+    } else {
+        ui.visuals().strong_text_color()
+    };
+    painter.line_segment(
+        [center, point_at(articulation_angle.angle, radius - 8.0)],
+        egui::Stroke::new(3.0, needle_color),
+    );
+    painter.text(
+        center + egui::vec2(0.0, 4.0),
+        egui::Align2::CENTER_TOP,
+        format!("{:.0}°", articulation_angle.angle.to_degrees()),
+        egui::FontId::proportional(16.0),
+        needle_color,
+    );
+}